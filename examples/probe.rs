@@ -1,7 +1,12 @@
-use iprobe::{ipv4, ipv4_mapped_ipv6, ipv6};
+use iprobe::{ipv4, ipv4_mapped_ipv6, ipv6, probe};
 
 fn main() {
   println!("IPv4 enabled: {}", ipv4());
   println!("IPv6 enabled: {}", ipv6());
   println!("IPv4-mapped IPv6 enabled: {}", ipv4_mapped_ipv6());
+
+  let udp = probe().udp();
+  println!("UDP IPv4 enabled: {}", udp.ipv4());
+  println!("UDP IPv6 enabled: {}", udp.ipv6());
+  println!("UDP IPv4-mapped IPv6 enabled: {}", udp.ipv4_mapped_ipv6());
 }
@@ -0,0 +1,16 @@
+#[cfg(target_os = "linux")]
+fn main() -> std::io::Result<()> {
+  let watcher = iprobe::watch()?;
+  println!("Current: {:?}", watcher.current());
+
+  for change in watcher {
+    let change = change?;
+    println!("Changed: {:?} -> {:?}", change.old, change.new);
+  }
+  Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn main() {
+  eprintln!("watching is only supported on Linux");
+}
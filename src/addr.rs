@@ -0,0 +1,139 @@
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+
+use super::{probe, Probe};
+
+/// The policy used to pick an address family when both are usable.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+#[non_exhaustive]
+pub enum AddrPolicy {
+  /// Prefer IPv6, which is the default.
+  #[default]
+  PreferIpv6,
+  /// Prefer IPv4.
+  PreferIpv4,
+}
+
+impl Probe {
+  /// Returns the wildcard address to bind, according to `policy`.
+  ///
+  /// With [`AddrPolicy::PreferIpv6`], `::` is returned only if it also
+  /// accepts IPv4 peers, i.e. IPv4-mapped IPv6 is understood, or if IPv4 is
+  /// not supported at all. With [`AddrPolicy::PreferIpv4`], `0.0.0.0` is
+  /// returned unless only IPv6 is supported. Falls back to `0.0.0.0` if
+  /// neither family is supported.
+  pub const fn unspecified_addr(&self, policy: AddrPolicy) -> IpAddr {
+    let ipv6 = match policy {
+      AddrPolicy::PreferIpv6 => self.ipv6() && (self.ipv4_mapped_ipv6() || !self.ipv4()),
+      AddrPolicy::PreferIpv4 => self.ipv6() && !self.ipv4(),
+    };
+
+    if ipv6 {
+      IpAddr::V6(Ipv6Addr::UNSPECIFIED)
+    } else {
+      IpAddr::V4(Ipv4Addr::UNSPECIFIED)
+    }
+  }
+
+  /// Returns the loopback address to connect to, according to `policy`.
+  ///
+  /// Returns the loopback address of the preferred family if it is
+  /// supported, otherwise the one of the other family. Falls back to
+  /// `127.0.0.1` if neither family is supported.
+  pub const fn loopback_addr(&self, policy: AddrPolicy) -> IpAddr {
+    let ipv6 = match policy {
+      AddrPolicy::PreferIpv6 => self.ipv6(),
+      AddrPolicy::PreferIpv4 => self.ipv6() && !self.ipv4(),
+    };
+
+    if ipv6 {
+      IpAddr::V6(Ipv6Addr::LOCALHOST)
+    } else {
+      IpAddr::V4(Ipv4Addr::LOCALHOST)
+    }
+  }
+
+  /// Returns [`Probe::unspecified_addr`] with the given `port`.
+  #[inline]
+  pub const fn unspecified_socket_addr(&self, policy: AddrPolicy, port: u16) -> SocketAddr {
+    SocketAddr::new(self.unspecified_addr(policy), port)
+  }
+
+  /// Returns [`Probe::loopback_addr`] with the given `port`.
+  #[inline]
+  pub const fn loopback_socket_addr(&self, policy: AddrPolicy, port: u16) -> SocketAddr {
+    SocketAddr::new(self.loopback_addr(policy), port)
+  }
+}
+
+/// Returns the preferred wildcard address to bind, which is `::` if it
+/// accepts both IPv4 and IPv6 peers, otherwise `0.0.0.0`.
+///
+/// See [`Probe::unspecified_addr`] to prefer IPv4.
+#[inline]
+pub fn unspecified_addr() -> IpAddr {
+  probe().unspecified_addr(AddrPolicy::default())
+}
+
+/// Returns the preferred loopback address to connect to, which is `::1` if
+/// IPv6 is supported, otherwise `127.0.0.1`.
+///
+/// See [`Probe::loopback_addr`] to prefer IPv4.
+#[inline]
+pub fn loopback_addr() -> IpAddr {
+  probe().loopback_addr(AddrPolicy::default())
+}
+
+/// Returns [`unspecified_addr`] with the given `port`.
+#[inline]
+pub fn unspecified_socket_addr(port: u16) -> SocketAddr {
+  SocketAddr::new(unspecified_addr(), port)
+}
+
+/// Returns [`loopback_addr`] with the given `port`.
+#[inline]
+pub fn loopback_socket_addr(port: u16) -> SocketAddr {
+  SocketAddr::new(loopback_addr(), port)
+}
+
+#[test]
+fn test_addrs() {
+  use super::Capabilities;
+
+  let v4 = IpAddr::V4(Ipv4Addr::UNSPECIFIED);
+  let v6 = IpAddr::V6(Ipv6Addr::UNSPECIFIED);
+
+  let dual = Probe::from_capabilities(Capabilities::all());
+  assert_eq!(dual.unspecified_addr(AddrPolicy::PreferIpv6), v6);
+  assert_eq!(dual.unspecified_addr(AddrPolicy::PreferIpv4), v4);
+  assert_eq!(
+    dual.loopback_addr(AddrPolicy::PreferIpv6),
+    Ipv6Addr::LOCALHOST
+  );
+  assert_eq!(
+    dual.loopback_addr(AddrPolicy::PreferIpv4),
+    Ipv4Addr::LOCALHOST
+  );
+
+  let unmapped = Probe::from_capabilities(Capabilities::IPV4 | Capabilities::IPV6);
+  assert_eq!(unmapped.unspecified_addr(AddrPolicy::PreferIpv6), v4);
+  assert_eq!(
+    unmapped.loopback_addr(AddrPolicy::PreferIpv6),
+    Ipv6Addr::LOCALHOST
+  );
+
+  let ipv6_only = Probe::from_capabilities(Capabilities::IPV6);
+  assert_eq!(ipv6_only.unspecified_addr(AddrPolicy::PreferIpv4), v6);
+  assert_eq!(
+    ipv6_only.loopback_socket_addr(AddrPolicy::PreferIpv4, 80),
+    SocketAddr::new(Ipv6Addr::LOCALHOST.into(), 80)
+  );
+
+  let none = Probe::from_capabilities(Capabilities::empty());
+  assert_eq!(none.unspecified_addr(AddrPolicy::PreferIpv6), v4);
+  assert_eq!(
+    none.loopback_addr(AddrPolicy::PreferIpv6),
+    Ipv4Addr::LOCALHOST
+  );
+}
@@ -0,0 +1,808 @@
+//! Source and destination address selection as specified by
+//! [RFC 6724](https://www.rfc-editor.org/rfc/rfc6724), aware of the
+//! capabilities found by [`probe`].
+
+use core::{cmp::Ordering, fmt};
+use std::{
+  io,
+  net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, UdpSocket},
+};
+
+use super::{probe, Probe};
+
+/// The port used to look up source addresses, no packet is ever sent to it.
+const DISCARD_PORT: u16 = 9;
+
+/// The scope of an address, ordered from the smallest to the largest.
+///
+/// See [RFC 6724 section 3.1](https://www.rfc-editor.org/rfc/rfc6724#section-3.1).
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[non_exhaustive]
+pub enum Scope {
+  /// Interface-local scope, multicast only.
+  InterfaceLocal = 0x1,
+  /// Link-local scope, e.g. `fe80::/10`, `169.254.0.0/16` or loopback.
+  LinkLocal = 0x2,
+  /// Admin-local scope, multicast only.
+  AdminLocal = 0x4,
+  /// Site-local scope, e.g. the deprecated `fec0::/10`.
+  SiteLocal = 0x5,
+  /// Organization-local scope, multicast only.
+  OrganizationLocal = 0x8,
+  /// Global scope.
+  Global = 0xe,
+}
+
+impl Scope {
+  /// Returns the scope of `addr`.
+  ///
+  /// IPv4 and IPv4-mapped IPv6 addresses are link-local if they are
+  /// loopback or `169.254.0.0/16` addresses, and global otherwise.
+  pub fn of(addr: IpAddr) -> Self {
+    match addr {
+      IpAddr::V4(addr) => Self::of_ipv4(addr),
+      IpAddr::V6(addr) => match addr.to_ipv4_mapped() {
+        Some(addr) => Self::of_ipv4(addr),
+        None => Self::of_ipv6(addr),
+      },
+    }
+  }
+
+  fn of_ipv4(addr: Ipv4Addr) -> Self {
+    if addr.is_loopback() || addr.is_link_local() {
+      Self::LinkLocal
+    } else {
+      Self::Global
+    }
+  }
+
+  fn of_ipv6(addr: Ipv6Addr) -> Self {
+    let first = addr.segments()[0];
+    if addr.is_multicast() {
+      // Unassigned values are rounded up to the next assigned scope.
+      return match first & 0xf {
+        0x1 => Self::InterfaceLocal,
+        0x2 => Self::LinkLocal,
+        0x3 | 0x4 => Self::AdminLocal,
+        0x5..=0x7 => Self::SiteLocal,
+        0x8..=0xd => Self::OrganizationLocal,
+        _ => Self::Global,
+      };
+    }
+
+    if addr.is_loopback() || first & 0xffc0 == 0xfe80 {
+      Self::LinkLocal
+    } else if first & 0xffc0 == 0xfec0 {
+      Self::SiteLocal
+    } else {
+      Self::Global
+    }
+  }
+}
+
+/// A row of a [`PolicyTable`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct PolicyEntry {
+  prefix: Ipv6Addr,
+  len: u8,
+  precedence: u8,
+  label: u8,
+}
+
+impl PolicyEntry {
+  /// Creates an entry matching `prefix/len`, IPv4 addresses are matched as
+  /// IPv4-mapped IPv6 addresses. `len` is capped at 128.
+  #[inline]
+  pub const fn new(prefix: Ipv6Addr, len: u8, precedence: u8, label: u8) -> Self {
+    Self {
+      prefix,
+      len: if len > 128 { 128 } else { len },
+      precedence,
+      label,
+    }
+  }
+
+  /// Returns the prefix of the entry.
+  #[inline]
+  pub const fn prefix(&self) -> Ipv6Addr {
+    self.prefix
+  }
+
+  /// Returns the length of the prefix of the entry.
+  #[inline]
+  pub const fn prefix_len(&self) -> u8 {
+    self.len
+  }
+
+  /// Returns the precedence of the entry.
+  #[inline]
+  pub const fn precedence(&self) -> u8 {
+    self.precedence
+  }
+
+  /// Returns the label of the entry.
+  #[inline]
+  pub const fn label(&self) -> u8 {
+    self.label
+  }
+
+  fn matches(&self, addr: Ipv6Addr) -> bool {
+    let mask = u128::MAX
+      .checked_shl(128 - u32::from(self.len))
+      .unwrap_or(0);
+    (u128::from(addr) ^ u128::from(self.prefix)) & mask == 0
+  }
+}
+
+/// The policy table used to sort destination addresses, which maps address
+/// prefixes to precedences and labels.
+///
+/// See [RFC 6724 section 2.1](https://www.rfc-editor.org/rfc/rfc6724#section-2.1).
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct PolicyTable {
+  /// Sorted by prefix length in descending order.
+  entries: Vec<PolicyEntry>,
+}
+
+impl Default for PolicyTable {
+  #[inline]
+  fn default() -> Self {
+    Self::rfc6724()
+  }
+}
+
+impl PolicyTable {
+  /// Creates a policy table from `entries`.
+  ///
+  /// Addresses not matched by any entry have the lowest precedence and
+  /// never match the label of another address.
+  pub fn new(entries: impl IntoIterator<Item = PolicyEntry>) -> Self {
+    let mut entries: Vec<_> = entries.into_iter().collect();
+    entries.sort_by_key(|entry| core::cmp::Reverse(entry.len));
+    Self { entries }
+  }
+
+  /// Returns the default policy table of RFC 6724.
+  pub fn rfc6724() -> Self {
+    const fn v6(a: u16, b: u16, f: u16) -> Ipv6Addr {
+      Ipv6Addr::new(a, b, 0, 0, 0, f, 0, 0)
+    }
+
+    Self::new([
+      PolicyEntry::new(Ipv6Addr::LOCALHOST, 128, 50, 0),
+      PolicyEntry::new(Ipv6Addr::UNSPECIFIED, 0, 40, 1),
+      PolicyEntry::new(v6(0, 0, 0xffff), 96, 35, 4),
+      PolicyEntry::new(v6(0x2002, 0, 0), 16, 30, 2),
+      PolicyEntry::new(v6(0x2001, 0, 0), 32, 5, 5),
+      PolicyEntry::new(v6(0xfc00, 0, 0), 7, 3, 13),
+      PolicyEntry::new(Ipv6Addr::UNSPECIFIED, 96, 1, 3),
+      PolicyEntry::new(v6(0xfec0, 0, 0), 10, 1, 11),
+      PolicyEntry::new(v6(0x3ffe, 0, 0), 16, 1, 12),
+    ])
+  }
+
+  /// Returns the entries of the table, longest prefixes first.
+  #[inline]
+  pub fn entries(&self) -> &[PolicyEntry] {
+    &self.entries
+  }
+
+  /// Returns the entry with the longest prefix matching `addr`.
+  pub fn lookup(&self, addr: IpAddr) -> Option<&PolicyEntry> {
+    let addr = match addr {
+      IpAddr::V4(addr) => addr.to_ipv6_mapped(),
+      IpAddr::V6(addr) => addr,
+    };
+    self.entries.iter().find(|entry| entry.matches(addr))
+  }
+}
+
+/// Sorts `addrs` from the most to the least preferred destination, using
+/// the default policy table.
+///
+/// See [`sort_destinations_with`].
+pub fn sort_destinations(addrs: &mut [SocketAddr]) {
+  sort_destinations_with(addrs, &PolicyTable::default());
+}
+
+/// Sorts `addrs` from the most to the least preferred destination according
+/// to RFC 6724 section 6, using `table`.
+///
+/// The source address of each destination is the one the kernel picks for
+/// a UDP socket connected to it, no packet is sent. Destinations without a
+/// source address, or of a family which is not supported according to
+/// [`probe`], are moved last. Rules 3, 4 and 7 are not applied, since the
+/// information they need is not available. The sort is stable.
+pub fn sort_destinations_with(addrs: &mut [SocketAddr], table: &PolicyTable) {
+  sort_by_source(addrs, table, probe(), |dest| {
+    source_address_for_socket_addr(dest).ok()
+  });
+}
+
+/// Returns the source address the kernel picks to reach `dest`.
+///
+/// A UDP socket is connected to `dest`, which sends no packet, and its
+/// local address is returned. Fails if there is no route to `dest`. Use
+/// [`select_source`] to explain the choice against the RFC 6724 rules.
+///
+/// Link-local IPv6 destinations need a scope id, which [`IpAddr`] cannot
+/// carry, use [`source_address_for_socket_addr`] for them.
+pub fn source_address_for(dest: IpAddr) -> io::Result<IpAddr> {
+  source_address_for_socket_addr(SocketAddr::new(dest, DISCARD_PORT))
+}
+
+/// Returns the source address the kernel picks to reach `dest`, see
+/// [`source_address_for`]. The port of `dest` is ignored.
+pub fn source_address_for_socket_addr(mut dest: SocketAddr) -> io::Result<IpAddr> {
+  let unspecified = match dest {
+    SocketAddr::V4(_) => SocketAddr::new(Ipv4Addr::UNSPECIFIED.into(), 0),
+    SocketAddr::V6(_) => SocketAddr::new(Ipv6Addr::UNSPECIFIED.into(), 0),
+  };
+  // Some systems refuse to connect to port 0.
+  dest.set_port(DISCARD_PORT);
+
+  let sock = UdpSocket::bind(unspecified)?;
+  sock.connect(dest)?;
+  sock.local_addr().map(|addr| addr.ip())
+}
+
+/// A destination and the attributes the rules compare.
+struct Destination {
+  addr: SocketAddr,
+  source: Option<IpAddr>,
+  scope: Scope,
+  precedence: u8,
+  label: Option<u8>,
+}
+
+fn sort_by_source(
+  addrs: &mut [SocketAddr],
+  table: &PolicyTable,
+  probe: Probe,
+  mut source: impl FnMut(SocketAddr) -> Option<IpAddr>,
+) {
+  let mut dests: Vec<_> = addrs
+    .iter()
+    .map(|&addr| {
+      let entry = table.lookup(addr.ip());
+      Destination {
+        addr,
+        source: if is_supported(probe, addr.ip()) {
+          source(addr)
+        } else {
+          None
+        },
+        scope: Scope::of(addr.ip()),
+        precedence: entry.map_or(0, PolicyEntry::precedence),
+        label: entry.map(PolicyEntry::label),
+      }
+    })
+    .collect();
+
+  dests.sort_by(|a, b| compare(a, b, table));
+
+  for (addr, dest) in addrs.iter_mut().zip(dests) {
+    *addr = dest.addr;
+  }
+}
+
+pub(crate) fn is_supported(probe: Probe, addr: IpAddr) -> bool {
+  match addr {
+    IpAddr::V4(_) => probe.ipv4(),
+    IpAddr::V6(addr) if addr.to_ipv4_mapped().is_some() => probe.ipv4_mapped_ipv6(),
+    IpAddr::V6(_) => probe.ipv6(),
+  }
+}
+
+/// Returns [`Ordering::Less`] if `a` is preferred over `b`.
+fn compare(a: &Destination, b: &Destination, table: &PolicyTable) -> Ordering {
+  // Rule 1: avoid unusable destinations.
+  let (sa, sb) = match (a.source, b.source) {
+    (Some(sa), Some(sb)) => (sa, sb),
+    (Some(_), None) => return Ordering::Less,
+    (None, Some(_)) => return Ordering::Greater,
+    (None, None) => return Ordering::Equal,
+  };
+
+  // Rule 2: prefer matching scope.
+  let matching_scope = |dest: &Destination, source| dest.scope == Scope::of(source);
+  match (matching_scope(a, sa), matching_scope(b, sb)) {
+    (true, false) => return Ordering::Less,
+    (false, true) => return Ordering::Greater,
+    _ => {}
+  }
+
+  // Rule 5: prefer matching label.
+  let matching_label = |dest: &Destination, source| {
+    dest.label.is_some() && dest.label == table.lookup(source).map(PolicyEntry::label)
+  };
+  match (matching_label(a, sa), matching_label(b, sb)) {
+    (true, false) => return Ordering::Less,
+    (false, true) => return Ordering::Greater,
+    _ => {}
+  }
+
+  // Rule 6: prefer higher precedence, then rule 8: prefer smaller scope.
+  let ord = b.precedence.cmp(&a.precedence).then(a.scope.cmp(&b.scope));
+  if ord.is_ne() {
+    return ord;
+  }
+
+  // Rule 9: use longest matching prefix.
+  if a.addr.is_ipv4() == b.addr.is_ipv4() {
+    let ord = common_prefix_len(sb, b.addr.ip()).cmp(&common_prefix_len(sa, a.addr.ip()));
+    if ord.is_ne() {
+      return ord;
+    }
+  }
+
+  // Rule 10: otherwise, leave the order unchanged.
+  Ordering::Equal
+}
+
+/// Returns the length of the common prefix of `source` and `dest`, which
+/// is capped at 64 for IPv6, the length of the interface identifier.
+fn common_prefix_len(source: IpAddr, dest: IpAddr) -> u32 {
+  match (source, dest) {
+    (IpAddr::V4(s), IpAddr::V4(d)) => (u32::from(s) ^ u32::from(d)).leading_zeros(),
+    (IpAddr::V6(s), IpAddr::V6(d)) => (u128::from(s) ^ u128::from(d)).leading_zeros().min(64),
+    _ => 0,
+  }
+}
+
+/// A local address considered by [`select_source`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct SourceCandidate {
+  addr: IpAddr,
+  deprecated: bool,
+  temporary: bool,
+}
+
+impl SourceCandidate {
+  /// Creates a preferred, non-temporary candidate.
+  #[inline]
+  pub const fn new(addr: IpAddr) -> Self {
+    Self {
+      addr,
+      deprecated: false,
+      temporary: false,
+    }
+  }
+
+  /// Returns the address of the candidate.
+  #[inline]
+  pub const fn addr(&self) -> IpAddr {
+    self.addr
+  }
+
+  /// Returns `true` if the preferred lifetime of the address has expired.
+  #[inline]
+  pub const fn deprecated(&self) -> bool {
+    self.deprecated
+  }
+
+  /// Marks the address as deprecated.
+  #[inline]
+  pub const fn with_deprecated(mut self, deprecated: bool) -> Self {
+    self.deprecated = deprecated;
+    self
+  }
+
+  /// Returns `true` if the address is a temporary privacy address.
+  #[inline]
+  pub const fn temporary(&self) -> bool {
+    self.temporary
+  }
+
+  /// Marks the address as a temporary privacy address, see RFC 8981.
+  #[inline]
+  pub const fn with_temporary(mut self, temporary: bool) -> Self {
+    self.temporary = temporary;
+    self
+  }
+}
+
+impl From<IpAddr> for SourceCandidate {
+  #[inline]
+  fn from(addr: IpAddr) -> Self {
+    Self::new(addr)
+  }
+}
+
+/// A rule of RFC 6724 section 5 which decided between source addresses.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum SourceRule {
+  /// Rule 1: prefer the destination address itself.
+  SameAddress,
+  /// Rule 2: prefer the address whose scope fits the destination.
+  AppropriateScope,
+  /// Rule 3: avoid deprecated addresses.
+  AvoidDeprecated,
+  /// Rule 6: prefer the address whose label matches the destination.
+  MatchingLabel,
+  /// Rule 7: prefer temporary addresses.
+  PreferTemporary,
+  /// Rule 8: prefer the longest prefix shared with the destination.
+  LongestMatchingPrefix,
+}
+
+impl SourceRule {
+  /// Returns a short human readable description of the rule.
+  #[inline]
+  pub const fn as_str(&self) -> &'static str {
+    match self {
+      Self::SameAddress => "prefer same address",
+      Self::AppropriateScope => "prefer appropriate scope",
+      Self::AvoidDeprecated => "avoid deprecated addresses",
+      Self::MatchingLabel => "prefer matching label",
+      Self::PreferTemporary => "prefer temporary addresses",
+      Self::LongestMatchingPrefix => "use longest matching prefix",
+    }
+  }
+}
+
+impl fmt::Display for SourceRule {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.write_str(self.as_str())
+  }
+}
+
+/// The source address chosen by [`select_source`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct SourceSelection {
+  candidate: SourceCandidate,
+  rule: Option<SourceRule>,
+}
+
+impl SourceSelection {
+  /// Returns the chosen address.
+  #[inline]
+  pub const fn addr(&self) -> IpAddr {
+    self.candidate.addr
+  }
+
+  /// Returns the chosen candidate.
+  #[inline]
+  pub const fn candidate(&self) -> SourceCandidate {
+    self.candidate
+  }
+
+  /// Returns the rule which preferred the chosen candidate over the
+  /// runner-up, or `None` if there was no other candidate or no rule
+  /// decided between them.
+  #[inline]
+  pub const fn rule(&self) -> Option<SourceRule> {
+    self.rule
+  }
+}
+
+/// Selects the source address to reach `dest` among `candidates` according
+/// to RFC 6724 section 5, using `table`.
+///
+/// Candidates of the other family than `dest` are ignored, IPv4-mapped
+/// IPv6 addresses count as IPv4. Rules 4, 5 and 5.5 are not applied, since
+/// the information they need is not available. Ties are broken by the order
+/// of `candidates`. Returns `None` if no candidate is eligible.
+pub fn select_source(
+  dest: IpAddr,
+  candidates: impl IntoIterator<Item = SourceCandidate>,
+  table: &PolicyTable,
+) -> Option<SourceSelection> {
+  let is_ipv4 = |addr: IpAddr| match addr {
+    IpAddr::V4(_) => true,
+    IpAddr::V6(addr) => addr.to_ipv4_mapped().is_some(),
+  };
+
+  let mut candidates: Vec<_> = candidates
+    .into_iter()
+    .filter(|candidate| is_ipv4(candidate.addr) == is_ipv4(dest))
+    .collect();
+  candidates.sort_by(|a, b| compare_sources(a, b, dest, table).0);
+
+  let mut candidates = candidates.into_iter();
+  let candidate = candidates.next()?;
+  Some(SourceSelection {
+    candidate,
+    rule: candidates
+      .next()
+      .and_then(|runner_up| compare_sources(&candidate, &runner_up, dest, table).1),
+  })
+}
+
+/// Returns [`Ordering::Less`] if `a` is preferred over `b`, and the rule
+/// which decided.
+fn compare_sources(
+  a: &SourceCandidate,
+  b: &SourceCandidate,
+  dest: IpAddr,
+  table: &PolicyTable,
+) -> (Ordering, Option<SourceRule>) {
+  let prefer = |a: bool, b: bool| match (a, b) {
+    (true, false) => Some(Ordering::Less),
+    (false, true) => Some(Ordering::Greater),
+    _ => None,
+  };
+
+  // Rule 1: prefer same address.
+  if let Some(ord) = prefer(a.addr == dest, b.addr == dest) {
+    return (ord, Some(SourceRule::SameAddress));
+  }
+
+  // Rule 2: prefer appropriate scope, i.e. the smallest scope which is
+  // at least the scope of the destination.
+  let (sa, sb, sd) = (Scope::of(a.addr), Scope::of(b.addr), Scope::of(dest));
+  let ord = match sa.cmp(&sb) {
+    Ordering::Less if sa < sd => Ordering::Greater,
+    Ordering::Less => Ordering::Less,
+    Ordering::Greater if sb < sd => Ordering::Less,
+    Ordering::Greater => Ordering::Greater,
+    Ordering::Equal => Ordering::Equal,
+  };
+  if ord.is_ne() {
+    return (ord, Some(SourceRule::AppropriateScope));
+  }
+
+  // Rule 3: avoid deprecated addresses.
+  if let Some(ord) = prefer(!a.deprecated, !b.deprecated) {
+    return (ord, Some(SourceRule::AvoidDeprecated));
+  }
+
+  // Rule 6: prefer matching label.
+  let label = |addr| table.lookup(addr).map(PolicyEntry::label);
+  let dest_label = label(dest);
+  let matching_label = |addr| dest_label.is_some() && label(addr) == dest_label;
+  if let Some(ord) = prefer(matching_label(a.addr), matching_label(b.addr)) {
+    return (ord, Some(SourceRule::MatchingLabel));
+  }
+
+  // Rule 7: prefer temporary addresses.
+  if let Some(ord) = prefer(a.temporary, b.temporary) {
+    return (ord, Some(SourceRule::PreferTemporary));
+  }
+
+  // Rule 8: use longest matching prefix.
+  let ord = common_prefix_len(b.addr, dest).cmp(&common_prefix_len(a.addr, dest));
+  if ord.is_ne() {
+    return (ord, Some(SourceRule::LongestMatchingPrefix));
+  }
+
+  (Ordering::Equal, None)
+}
+
+#[cfg(test)]
+fn sorted(dests: &[&str], sources: &[(&str, &str)], probe: Probe) -> Vec<IpAddr> {
+  let ip = |s: &str| s.parse::<IpAddr>().unwrap();
+  let mut addrs: Vec<_> = dests.iter().map(|d| SocketAddr::new(ip(d), 0)).collect();
+  sort_by_source(&mut addrs, &PolicyTable::default(), probe, |dest| {
+    sources
+      .iter()
+      .find(|(d, _)| ip(d) == dest.ip())
+      .map(|(_, s)| ip(s))
+  });
+  addrs.into_iter().map(|addr| addr.ip()).collect()
+}
+
+#[test]
+fn test_scope() {
+  let scope = |s: &str| Scope::of(s.parse().unwrap());
+  assert_eq!(scope("::1"), Scope::LinkLocal);
+  assert_eq!(scope("fe80::1"), Scope::LinkLocal);
+  assert_eq!(scope("fec0::1"), Scope::SiteLocal);
+  assert_eq!(scope("ff05::1"), Scope::SiteLocal);
+  assert_eq!(scope("2001:db8::1"), Scope::Global);
+  assert_eq!(scope("127.0.0.1"), Scope::LinkLocal);
+  assert_eq!(scope("::ffff:169.254.1.1"), Scope::LinkLocal);
+  assert_eq!(scope("10.0.0.1"), Scope::Global);
+
+  let table = PolicyTable::default();
+  let label = |s: &str| table.lookup(s.parse().unwrap()).unwrap().label();
+  assert_eq!(label("::1"), 0);
+  assert_eq!(label("10.0.0.1"), 4);
+  assert_eq!(label("2002::1"), 2);
+  assert_eq!(label("fd00::1"), 13);
+  assert_eq!(label("2001:db8::1"), 1);
+}
+
+#[test]
+fn test_sort_destinations() {
+  use crate::Capabilities;
+
+  let all = Probe::from_capabilities(Capabilities::all());
+  let ip = |s: &str| s.parse::<IpAddr>().unwrap();
+
+  // The destinations, their sources and the expected order.
+  type Example = (
+    &'static [&'static str],
+    &'static [(&'static str, &'static str)],
+    [&'static str; 2],
+  );
+
+  // The examples of RFC 6724 section 10.2.
+  let examples: [Example; 6] = [
+    // Prefer matching scope.
+    (
+      &["2001:db8:1::1", "198.51.100.121"],
+      &[
+        ("2001:db8:1::1", "2001:db8:1::2"),
+        ("198.51.100.121", "169.254.13.78"),
+      ],
+      ["2001:db8:1::1", "198.51.100.121"],
+    ),
+    (
+      &["2001:db8:1::1", "198.51.100.121"],
+      &[
+        ("2001:db8:1::1", "fe80::1"),
+        ("198.51.100.121", "198.51.100.117"),
+      ],
+      ["198.51.100.121", "2001:db8:1::1"],
+    ),
+    // Prefer higher precedence.
+    (
+      &["2001:db8:1::1", "10.1.2.3"],
+      &[("2001:db8:1::1", "2001:db8:1::2"), ("10.1.2.3", "10.1.2.4")],
+      ["2001:db8:1::1", "10.1.2.3"],
+    ),
+    // Prefer smaller scope.
+    (
+      &["2001:db8:1::1", "fe80::1"],
+      &[("2001:db8:1::1", "2001:db8:1::2"), ("fe80::1", "fe80::2")],
+      ["fe80::1", "2001:db8:1::1"],
+    ),
+    // Prefer matching label.
+    (
+      &["2001:db8:1::1", "2002:c633:6401::1"],
+      &[
+        ("2001:db8:1::1", "2002:c633:6401::2"),
+        ("2002:c633:6401::1", "2002:c633:6401::2"),
+      ],
+      ["2002:c633:6401::1", "2001:db8:1::1"],
+    ),
+    // Prefer higher precedence.
+    (
+      &["2001:db8:1::1", "2002:c633:6401::1"],
+      &[
+        ("2001:db8:1::1", "2001:db8:1::2"),
+        ("2002:c633:6401::1", "2002:c633:6401::2"),
+      ],
+      ["2001:db8:1::1", "2002:c633:6401::1"],
+    ),
+  ];
+  for (dests, sources, expected) in examples {
+    assert_eq!(sorted(dests, sources, all), expected.map(ip));
+  }
+
+  // Avoid unusable destinations.
+  let ipv4_only = Probe::from_capabilities(Capabilities::IPV4 | Capabilities::UDP_IPV4);
+  let sources = [("2001:db8:1::1", "2001:db8:1::2"), ("10.1.2.3", "10.1.2.4")];
+  assert_eq!(
+    sorted(&["2001:db8:1::1", "10.1.2.3"], &sources, ipv4_only),
+    [ip("10.1.2.3"), ip("2001:db8:1::1")]
+  );
+  assert_eq!(
+    sorted(&["2001:db8:1::1", "10.1.2.3"], &sources[1..], all),
+    [ip("10.1.2.3"), ip("2001:db8:1::1")]
+  );
+
+  // A custom table reverses the precedence of IPv4 and IPv6.
+  let mut addrs = [
+    SocketAddr::new(ip("2001:db8:1::1"), 0),
+    SocketAddr::new(ip("10.1.2.3"), 0),
+  ];
+  let table = PolicyTable::new([
+    PolicyEntry::new(Ipv6Addr::UNSPECIFIED, 0, 40, 1),
+    PolicyEntry::new(Ipv4Addr::UNSPECIFIED.to_ipv6_mapped(), 96, 45, 4),
+  ]);
+  sort_by_source(&mut addrs, &table, all, |dest| match dest.ip() {
+    IpAddr::V4(_) => Some(ip("10.1.2.4")),
+    IpAddr::V6(_) => Some(ip("2001:db8:1::2")),
+  });
+  assert_eq!(addrs[0].ip(), ip("10.1.2.3"));
+}
+
+#[test]
+fn test_select_source() {
+  let ip = |s: &str| s.parse::<IpAddr>().unwrap();
+  let select = |dest, candidates: &[SourceCandidate]| {
+    let selection = select_source(
+      ip(dest),
+      candidates.iter().copied(),
+      &PolicyTable::default(),
+    );
+    selection.map(|selection| (selection.addr(), selection.rule()))
+  };
+  let candidate = |s| SourceCandidate::new(ip(s));
+
+  // The examples of RFC 6724 section 10.1.
+  assert_eq!(
+    select(
+      "2001:db8:1::1",
+      &[candidate("2001:db8:3::1"), candidate("fe80::1")]
+    ),
+    Some((ip("2001:db8:3::1"), Some(SourceRule::AppropriateScope)))
+  );
+  assert_eq!(
+    select(
+      "ff05::1",
+      &[candidate("fe80::1"), candidate("2001:db8:3::1")]
+    ),
+    Some((ip("2001:db8:3::1"), Some(SourceRule::AppropriateScope)))
+  );
+  assert_eq!(
+    select(
+      "2001:db8:1::1",
+      &[candidate("2001:db8:2::1"), candidate("2001:db8:1::1")]
+    ),
+    Some((ip("2001:db8:1::1"), Some(SourceRule::SameAddress)))
+  );
+  assert_eq!(
+    select(
+      "fe80::1",
+      &[candidate("2001:db8:1::1"), candidate("fe80::2")]
+    ),
+    Some((ip("fe80::2"), Some(SourceRule::AppropriateScope)))
+  );
+  assert_eq!(
+    select(
+      "2001:db8:1::1",
+      &[candidate("2001:db8:3::2"), candidate("2001:db8:1::2")]
+    ),
+    Some((ip("2001:db8:1::2"), Some(SourceRule::LongestMatchingPrefix)))
+  );
+  assert_eq!(
+    select(
+      "2002:836b:4179::2",
+      &[
+        candidate("2001:db8:1::2"),
+        candidate("2002:836b:4179::d5e3:7953:13eb:22e8"),
+      ]
+    ),
+    Some((
+      ip("2002:836b:4179::d5e3:7953:13eb:22e8"),
+      Some(SourceRule::MatchingLabel)
+    ))
+  );
+  assert_eq!(
+    select(
+      "2001:db8:1::d5e3:0:0:1",
+      &[
+        candidate("2001:db8:1::2"),
+        candidate("2001:db8:1::d5e3:7953:13eb:22e8").with_temporary(true),
+      ]
+    ),
+    Some((
+      ip("2001:db8:1::d5e3:7953:13eb:22e8"),
+      Some(SourceRule::PreferTemporary)
+    ))
+  );
+
+  assert_eq!(
+    select(
+      "2001:db8:1::1",
+      &[
+        candidate("2001:db8:1::2").with_deprecated(true),
+        candidate("2001:db8:3::2"),
+      ]
+    ),
+    Some((ip("2001:db8:3::2"), Some(SourceRule::AvoidDeprecated)))
+  );
+  assert_eq!(
+    select("10.0.0.1", &[candidate("fe80::1"), candidate("10.0.0.2")]),
+    Some((ip("10.0.0.2"), None))
+  );
+  assert_eq!(select("10.0.0.1", &[candidate("fe80::1")]), None);
+}
+
+#[test]
+fn test_source_address_for() {
+  if probe().ipv4() {
+    let addr = IpAddr::V4(Ipv4Addr::LOCALHOST);
+    assert_eq!(source_address_for(addr).unwrap(), addr);
+    let selection = select_source(addr, [SourceCandidate::new(addr)], &PolicyTable::default());
+    assert_eq!(selection.unwrap().addr(), addr);
+  }
+  if probe().ipv6() {
+    let addr = IpAddr::V6(Ipv6Addr::LOCALHOST);
+    assert_eq!(source_address_for(addr).unwrap(), addr);
+  }
+}
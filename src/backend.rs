@@ -0,0 +1,433 @@
+use core::cell::Cell;
+use std::{
+  net::{Ipv4Addr, Ipv6Addr, SocketAddr},
+  time::Instant,
+};
+
+use rustix::{
+  event::{poll, PollFd, PollFlags, Timespec},
+  fd::{AsFd, OwnedFd},
+  io::{ioctl_fionbio, Errno},
+  net::{sockopt, AddressFamily, Protocol, RecvFlags, SendFlags, SocketType},
+};
+
+use super::Step;
+
+/// The socket operations the checks of a [`ProbeBuilder`](crate::ProbeBuilder)
+/// are made of, to control what the checks see in tests.
+///
+/// Every socket check goes through the backend, including the connectivity
+/// and dual-stack listener checks. The IPv6 sysctls describe the host, so
+/// they are only read with backends whose [`SocketBackend::SYSTEM`] is
+/// `true`, and are `None` in the reports of other backends.
+pub trait SocketBackend {
+  /// The socket created by [`SocketBackend::socket`].
+  type Socket;
+
+  /// Whether the backend operates on the sockets of the host, e.g. a
+  /// wrapper of [`RustixBackend`] which logs the calls.
+  ///
+  /// The reports of such backends contain the IPv6 sysctls and the
+  /// overrides in effect, and `ProbeBuilder::report_async` waits for their
+  /// connectivity checks with tokio.
+  const SYSTEM: bool;
+
+  /// Creates a socket.
+  fn socket(
+    &self,
+    family: AddressFamily,
+    ty: SocketType,
+    protocol: Option<Protocol>,
+  ) -> Result<Self::Socket, Errno>;
+
+  /// Returns the `IPV6_V6ONLY` socket option.
+  fn ipv6_v6only(&self, sock: &Self::Socket) -> Result<bool, Errno>;
+
+  /// Sets the `IPV6_V6ONLY` socket option.
+  fn set_ipv6_v6only(&self, sock: &Self::Socket, v6only: bool) -> Result<(), Errno>;
+
+  /// Binds the socket to `addr`.
+  fn bind(&self, sock: &Self::Socket, addr: &SocketAddr) -> Result<(), Errno>;
+
+  /// Listens on the bound socket, returns the address it listens on.
+  fn listen(&self, sock: &Self::Socket) -> Result<SocketAddr, Errno>;
+
+  /// Connects the socket to `addr`, fails with `ETIMEDOUT` if the
+  /// connection is not established by `deadline`.
+  fn connect(&self, sock: &Self::Socket, addr: &SocketAddr, deadline: Instant)
+    -> Result<(), Errno>;
+
+  /// Accepts a connection on the listening socket, returns it and the
+  /// address of the peer. Fails with `ETIMEDOUT` if no client connects by
+  /// `deadline`.
+  fn accept(
+    &self,
+    sock: &Self::Socket,
+    deadline: Instant,
+  ) -> Result<(Self::Socket, SocketAddr), Errno>;
+
+  /// Sends `buf` over the connected socket, returns the number of bytes
+  /// sent.
+  fn send(&self, sock: &Self::Socket, buf: &[u8]) -> Result<usize, Errno>;
+
+  /// Receives into `buf` from the connected socket, returns the number of
+  /// bytes received. Fails with `ETIMEDOUT` if nothing arrives by
+  /// `deadline`.
+  fn recv(&self, sock: &Self::Socket, buf: &mut [u8], deadline: Instant) -> Result<usize, Errno>;
+}
+
+/// The backend which performs the system calls, used by default.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct RustixBackend;
+
+impl SocketBackend for RustixBackend {
+  type Socket = OwnedFd;
+
+  const SYSTEM: bool = true;
+
+  #[inline]
+  fn socket(
+    &self,
+    family: AddressFamily,
+    ty: SocketType,
+    protocol: Option<Protocol>,
+  ) -> Result<OwnedFd, Errno> {
+    rustix::net::socket(family, ty, protocol)
+  }
+
+  #[inline]
+  fn ipv6_v6only(&self, sock: &OwnedFd) -> Result<bool, Errno> {
+    sockopt::ipv6_v6only(sock)
+  }
+
+  #[inline]
+  fn set_ipv6_v6only(&self, sock: &OwnedFd, v6only: bool) -> Result<(), Errno> {
+    sockopt::set_ipv6_v6only(sock, v6only)
+  }
+
+  #[inline]
+  fn bind(&self, sock: &OwnedFd, addr: &SocketAddr) -> Result<(), Errno> {
+    rustix::net::bind(sock, addr)
+  }
+
+  fn listen(&self, sock: &OwnedFd) -> Result<SocketAddr, Errno> {
+    rustix::net::listen(sock, 1)?;
+    rustix::net::getsockname(sock)
+      .and_then(|addr| SocketAddr::try_from(addr).map_err(|_| Errno::AFNOSUPPORT))
+  }
+
+  fn connect(&self, sock: &OwnedFd, addr: &SocketAddr, deadline: Instant) -> Result<(), Errno> {
+    ioctl_fionbio(sock, true)?;
+    match rustix::net::connect(sock, addr) {
+      Ok(()) => Ok(()),
+      Err(Errno::INPROGRESS | Errno::WOULDBLOCK) => {
+        wait(sock, PollFlags::OUT, deadline)?;
+        sockopt::socket_error(sock)?
+      }
+      Err(e) => Err(e),
+    }
+  }
+
+  fn accept(&self, sock: &OwnedFd, deadline: Instant) -> Result<(OwnedFd, SocketAddr), Errno> {
+    ioctl_fionbio(sock, true)?;
+    wait(sock, PollFlags::IN, deadline)?;
+    let (conn, peer) = rustix::net::acceptfrom(sock)?;
+    let peer = peer
+      .and_then(|peer| SocketAddr::try_from(peer).ok())
+      .ok_or(Errno::AFNOSUPPORT)?;
+    Ok((conn, peer))
+  }
+
+  #[inline]
+  fn send(&self, sock: &OwnedFd, buf: &[u8]) -> Result<usize, Errno> {
+    rustix::net::send(sock, buf, SendFlags::empty())
+  }
+
+  fn recv(&self, sock: &OwnedFd, buf: &mut [u8], deadline: Instant) -> Result<usize, Errno> {
+    wait(sock, PollFlags::IN, deadline)?;
+    rustix::net::recv(sock, buf, RecvFlags::empty()).map(|(len, _)| len)
+  }
+}
+
+/// Waits until `fd` is ready for `flags`, or fails with `ETIMEDOUT` once
+/// `deadline` has passed.
+fn wait(fd: impl AsFd, flags: PollFlags, deadline: Instant) -> Result<(), Errno> {
+  loop {
+    let timeout = deadline.saturating_duration_since(Instant::now());
+    let timeout = Timespec::try_from(timeout).map_err(|_| Errno::INVAL)?;
+    let mut fds = [PollFd::new(&fd, flags)];
+    match poll(&mut fds, Some(&timeout)) {
+      Ok(0) => return Err(Errno::TIMEDOUT),
+      Ok(_) => return Ok(()),
+      Err(Errno::INTR) => {}
+      Err(e) => return Err(e),
+    }
+  }
+}
+
+/// A backend which never touches the system, and fails the steps it is
+/// told to fail.
+///
+/// Every step succeeds unless an error was scripted for it. Connections
+/// are accepted from a loopback client of the family of the listener, or
+/// from `::ffff:127.0.0.1` if the listener is a dual-stack IPv6 socket.
+/// Sent data is discarded, receiving succeeds with the buffer left as
+/// is. The IPv6 sysctls are not read, see [`SocketBackend`].
+///
+/// ```rust
+/// use iprobe::{MockBackend, ProbeBuilder, Step};
+/// use rustix::{io::Errno, net::AddressFamily};
+///
+/// // A host without IPv6 support.
+/// let backend = MockBackend::new().with_error(AddressFamily::INET6, Step::Socket, Errno::AFNOSUPPORT);
+/// let report = ProbeBuilder::new().with_backend(backend).report();
+/// assert!(report.probe().ipv4());
+/// assert!(!report.probe().ipv6());
+/// ```
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct MockBackend {
+  errors: Vec<MockError>,
+  default_v6only: bool,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+struct MockError {
+  family: AddressFamily,
+  v6only: Option<bool>,
+  step: Step,
+  errno: Errno,
+}
+
+impl MockBackend {
+  /// Creates a backend on which every step succeeds.
+  #[inline]
+  pub const fn new() -> Self {
+    Self {
+      errors: Vec::new(),
+      default_v6only: false,
+    }
+  }
+
+  /// Sets the `IPV6_V6ONLY` of new IPv6 sockets.
+  ///
+  /// Default is `false`, like on Linux.
+  #[inline]
+  pub fn with_default_v6only(mut self, v6only: bool) -> Self {
+    self.default_v6only = v6only;
+    self
+  }
+
+  /// Fails `step` with `errno` for sockets of `family`.
+  ///
+  /// The steps of connections, e.g. [`Step::Accept`], fail for the
+  /// listening socket or the client of `family`.
+  #[inline]
+  pub fn with_error(mut self, family: AddressFamily, step: Step, errno: Errno) -> Self {
+    self.errors.push(MockError {
+      family,
+      v6only: None,
+      step,
+      errno,
+    });
+    self
+  }
+
+  /// Fails `step` with `errno` for IPv6 sockets whose `IPV6_V6ONLY` is set
+  /// to `v6only`, e.g. the IPv4-mapped IPv6 checks with `false`.
+  ///
+  /// For [`Step::SetIpv6V6Only`], the value being set is matched.
+  #[inline]
+  pub fn with_v6only_error(mut self, v6only: bool, step: Step, errno: Errno) -> Self {
+    self.errors.push(MockError {
+      family: AddressFamily::INET6,
+      v6only: Some(v6only),
+      step,
+      errno,
+    });
+    self
+  }
+
+  fn result(&self, family: AddressFamily, v6only: Option<bool>, step: Step) -> Result<(), Errno> {
+    let error = self.errors.iter().find(|error| {
+      error.family == family
+        && error.step == step
+        && error.v6only.map_or(true, |value| Some(value) == v6only)
+    });
+    match error {
+      Some(error) => Err(error.errno),
+      None => Ok(()),
+    }
+  }
+}
+
+/// A socket of a [`MockBackend`].
+#[derive(Debug)]
+pub struct MockSocket {
+  family: AddressFamily,
+  v6only: Cell<Option<bool>>,
+  addr: Cell<Option<SocketAddr>>,
+}
+
+impl MockSocket {
+  /// Returns the family the socket was created with.
+  #[inline]
+  pub const fn family(&self) -> AddressFamily {
+    self.family
+  }
+
+  /// Returns the value `IPV6_V6ONLY` was set to, if it was.
+  #[inline]
+  pub fn v6only(&self) -> Option<bool> {
+    self.v6only.get()
+  }
+
+  /// Returns the address the socket was bound or connected to, if any.
+  #[inline]
+  pub fn addr(&self) -> Option<SocketAddr> {
+    self.addr.get()
+  }
+
+  const fn new(family: AddressFamily, addr: Option<SocketAddr>) -> Self {
+    Self {
+      family,
+      v6only: Cell::new(None),
+      addr: Cell::new(addr),
+    }
+  }
+}
+
+impl SocketBackend for MockBackend {
+  type Socket = MockSocket;
+
+  const SYSTEM: bool = false;
+
+  fn socket(
+    &self,
+    family: AddressFamily,
+    _ty: SocketType,
+    _protocol: Option<Protocol>,
+  ) -> Result<MockSocket, Errno> {
+    self.result(family, None, Step::Socket)?;
+    Ok(MockSocket::new(family, None))
+  }
+
+  fn ipv6_v6only(&self, sock: &MockSocket) -> Result<bool, Errno> {
+    self.result(sock.family, sock.v6only(), Step::GetIpv6V6Only)?;
+    Ok(sock.v6only().unwrap_or(self.default_v6only))
+  }
+
+  fn set_ipv6_v6only(&self, sock: &MockSocket, v6only: bool) -> Result<(), Errno> {
+    self.result(sock.family, Some(v6only), Step::SetIpv6V6Only)?;
+    sock.v6only.set(Some(v6only));
+    Ok(())
+  }
+
+  fn bind(&self, sock: &MockSocket, addr: &SocketAddr) -> Result<(), Errno> {
+    self.result(sock.family, sock.v6only(), Step::Bind)?;
+    sock.addr.set(Some(*addr));
+    Ok(())
+  }
+
+  fn listen(&self, sock: &MockSocket) -> Result<SocketAddr, Errno> {
+    self.result(sock.family, sock.v6only(), Step::Listen)?;
+    let mut addr = sock.addr().ok_or(Errno::INVAL)?;
+    if addr.port() == 0 {
+      addr.set_port(MOCK_PORT);
+    }
+    sock.addr.set(Some(addr));
+    Ok(addr)
+  }
+
+  fn connect(&self, sock: &MockSocket, addr: &SocketAddr, _deadline: Instant) -> Result<(), Errno> {
+    self.result(sock.family, sock.v6only(), Step::Connect)?;
+    sock.addr.set(Some(*addr));
+    Ok(())
+  }
+
+  fn accept(
+    &self,
+    sock: &MockSocket,
+    _deadline: Instant,
+  ) -> Result<(MockSocket, SocketAddr), Errno> {
+    self.result(sock.family, sock.v6only(), Step::Accept)?;
+    let addr = sock.addr().ok_or(Errno::INVAL)?;
+    let v6only = sock.v6only().unwrap_or(self.default_v6only);
+    let ip = match addr {
+      SocketAddr::V4(_) => Ipv4Addr::LOCALHOST.into(),
+      SocketAddr::V6(_) if v6only => Ipv6Addr::LOCALHOST.into(),
+      SocketAddr::V6(_) => Ipv4Addr::LOCALHOST.to_ipv6_mapped().into(),
+    };
+    let peer = SocketAddr::new(ip, MOCK_PORT + 1);
+    let conn = MockSocket::new(sock.family, Some(peer));
+    conn.v6only.set(sock.v6only());
+    Ok((conn, peer))
+  }
+
+  fn send(&self, sock: &MockSocket, buf: &[u8]) -> Result<usize, Errno> {
+    self.result(sock.family, sock.v6only(), Step::Send)?;
+    Ok(buf.len())
+  }
+
+  fn recv(&self, sock: &MockSocket, buf: &mut [u8], _deadline: Instant) -> Result<usize, Errno> {
+    self.result(sock.family, sock.v6only(), Step::Recv)?;
+    Ok(buf.len())
+  }
+}
+
+/// The port [`MockBackend`] listens on when bound to port 0.
+const MOCK_PORT: u16 = 49152;
+
+#[test]
+fn test_mock_backend() {
+  let backend = MockBackend::new()
+    .with_default_v6only(true)
+    .with_error(AddressFamily::INET, Step::Bind, Errno::NETUNREACH)
+    .with_v6only_error(false, Step::Bind, Errno::ADDRNOTAVAIL);
+  let addr = "[::1]:0".parse().unwrap();
+
+  let sock = backend
+    .socket(AddressFamily::INET6, SocketType::STREAM, None)
+    .unwrap();
+  assert_eq!(backend.ipv6_v6only(&sock), Ok(true));
+  backend.set_ipv6_v6only(&sock, true).unwrap();
+  assert_eq!(backend.bind(&sock, &addr), Ok(()));
+  backend.set_ipv6_v6only(&sock, false).unwrap();
+  assert_eq!(backend.ipv6_v6only(&sock), Ok(false));
+  assert_eq!(backend.bind(&sock, &addr), Err(Errno::ADDRNOTAVAIL));
+
+  let sock = backend
+    .socket(AddressFamily::INET, SocketType::DGRAM, None)
+    .unwrap();
+  assert_eq!(sock.v6only(), None);
+  assert_eq!(
+    backend.bind(&sock, &"127.0.0.1:0".parse().unwrap()),
+    Err(Errno::NETUNREACH)
+  );
+
+  let backend = MockBackend::new().with_error(AddressFamily::INET, Step::Connect, Errno::TIMEDOUT);
+  let deadline = Instant::now();
+  let listener = backend
+    .socket(AddressFamily::INET6, SocketType::STREAM, None)
+    .unwrap();
+  backend.set_ipv6_v6only(&listener, false).unwrap();
+  backend.bind(&listener, &"[::]:0".parse().unwrap()).unwrap();
+  let addr = backend.listen(&listener).unwrap();
+  assert_eq!(addr, "[::]:49152".parse().unwrap());
+  let client = backend
+    .socket(AddressFamily::INET6, SocketType::STREAM, None)
+    .unwrap();
+  backend.connect(&client, &addr, deadline).unwrap();
+  assert_eq!(client.addr(), Some(addr));
+  assert_eq!(backend.send(&client, &[1]), Ok(1));
+  let (conn, peer) = backend.accept(&listener, deadline).unwrap();
+  assert_eq!(peer, "[::ffff:127.0.0.1]:49153".parse().unwrap());
+  assert_eq!(backend.recv(&conn, &mut [0; 1], deadline), Ok(1));
+
+  let client = backend
+    .socket(AddressFamily::INET, SocketType::STREAM, None)
+    .unwrap();
+  assert_eq!(
+    backend.connect(&client, &"127.0.0.1:49152".parse().unwrap(), deadline),
+    Err(Errno::TIMEDOUT)
+  );
+}
@@ -0,0 +1,288 @@
+//! Probes the IP stack of the host, for container health checks and init
+//! scripts.
+
+use std::{fs, path::PathBuf, process::ExitCode, time::Duration};
+
+use clap::{error::ErrorKind, CommandFactory, Parser, ValueEnum};
+use iprobe::{Overrides, ProbeBuilder, ProbeDiff, ProbeError, ProbeReport, Snapshot};
+use serde_json::Value;
+
+/// Exit code when a required capability is missing, clap exits with 2 on
+/// usage errors.
+const EXIT_MISSING: u8 = 1;
+
+/// Probes whether the host supports IPv4, IPv6 and IPv4-mapped IPv6.
+///
+/// Exits with 0 if every capability passed to `--require` is available, 1
+/// if one is missing and 2 on usage errors.
+#[derive(Parser)]
+#[command(version)]
+struct Args {
+  /// Prints the snapshot written by `--save` as JSON, with the `missing`
+  /// capabilities and the `diff` of `--diff`.
+  #[arg(long)]
+  json: bool,
+  /// Also checks end-to-end loopback connectivity and dual-stack listeners.
+  #[arg(long)]
+  deep: bool,
+  /// Only reports the checks of a single address family, the JSON snapshot
+  /// always contains every family.
+  #[arg(long, value_enum, conflicts_with = "json")]
+  family: Option<Family>,
+  /// Exits with 1 unless the capability is available, may be repeated.
+  #[arg(long, value_enum)]
+  require: Vec<Capability>,
+  /// Milliseconds after which a deep check gives up.
+  #[arg(long, default_value_t = 1000)]
+  timeout: u64,
+  /// Writes a JSON snapshot of the report to the file.
+  #[arg(long, value_name = "FILE")]
+  save: Option<PathBuf>,
+  /// Prints the changes since a snapshot written with `--save`.
+  #[arg(long, value_name = "FILE")]
+  diff: Option<PathBuf>,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, ValueEnum)]
+enum Family {
+  V4,
+  V6,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, ValueEnum)]
+enum Capability {
+  Ipv4,
+  Ipv6,
+  Ipv4MappedIpv6,
+  /// An `[::]` listener accepts IPv4 clients.
+  DualStack,
+}
+
+impl Capability {
+  fn as_str(&self) -> &'static str {
+    match self {
+      Self::Ipv4 => "ipv4",
+      Self::Ipv6 => "ipv6",
+      Self::Ipv4MappedIpv6 => "ipv4-mapped-ipv6",
+      Self::DualStack => "dual-stack",
+    }
+  }
+
+  /// Returns the value the overrides force for the capability, if any.
+  fn forced(&self, overrides: Overrides) -> Option<bool> {
+    let probe = overrides.probe();
+    match self {
+      Self::Ipv4 => probe.map(|p| p.ipv4()).or(overrides.ipv4()),
+      Self::Ipv6 => probe.map(|p| p.ipv6()).or(overrides.ipv6()),
+      Self::Ipv4MappedIpv6 => probe
+        .map(|p| p.ipv4_mapped_ipv6())
+        .or(overrides.ipv4_mapped_ipv6()),
+      Self::DualStack => None,
+    }
+  }
+
+  /// Returns `true` if the report shows the capability, the deep checks
+  /// must pass as well if they were run. The overrides of the report win
+  /// over the checks, like in [`iprobe::probe`].
+  fn available(&self, report: &ProbeReport) -> bool {
+    if let Some(forced) = self.forced(report.overrides()) {
+      return forced;
+    }
+    let connectivity = report.connectivity();
+    let (bind, connect) = match self {
+      Self::Ipv4 => (report.ipv4(), connectivity.map(|c| c.ipv4())),
+      Self::Ipv6 => (report.ipv6(), connectivity.map(|c| c.ipv6())),
+      Self::Ipv4MappedIpv6 => (
+        report.ipv4_mapped_ipv6(),
+        connectivity.map(|c| c.ipv4_mapped_ipv6()),
+      ),
+      Self::DualStack => return report.dual_stack_listener_works(),
+    };
+    matches!(bind, Some(Ok(()))) && !matches!(connect, Some(Err(_)))
+  }
+}
+
+/// The results of a single family.
+struct Row {
+  name: &'static str,
+  family: Family,
+  tcp: Option<Result<(), ProbeError>>,
+  udp: Option<Result<(), ProbeError>>,
+  connect: Option<Result<(), ProbeError>>,
+}
+
+fn rows(report: &ProbeReport) -> [Row; 3] {
+  let (tcp, udp, connectivity) = (report.tcp(), report.udp(), report.connectivity());
+  [
+    Row {
+      name: "ipv4",
+      family: Family::V4,
+      tcp: tcp.map(|t| t.ipv4()),
+      udp: udp.map(|t| t.ipv4()),
+      connect: connectivity.map(|c| c.ipv4()),
+    },
+    Row {
+      name: "ipv6",
+      family: Family::V6,
+      tcp: tcp.map(|t| t.ipv6()),
+      udp: udp.map(|t| t.ipv6()),
+      connect: connectivity.map(|c| c.ipv6()),
+    },
+    Row {
+      name: "ipv4-mapped-ipv6",
+      family: Family::V6,
+      tcp: tcp.map(|t| t.ipv4_mapped_ipv6()),
+      udp: udp.map(|t| t.ipv4_mapped_ipv6()),
+      connect: connectivity.map(|c| c.ipv4_mapped_ipv6()),
+    },
+  ]
+}
+
+fn main() -> ExitCode {
+  let args = Args::parse();
+
+  let report = ProbeBuilder::new()
+    .with_deep(args.deep)
+    .with_dual_stack_listener(args.deep || args.require.contains(&Capability::DualStack))
+    .with_timeout(Duration::from_millis(args.timeout))
+    .report();
+
+  // Read the old snapshot first, `--save` may overwrite it.
+  let diff = args.diff.as_ref().map(|path| {
+    let old: Snapshot = fs::read_to_string(path)
+      .map_err(|e| e.to_string())
+      .and_then(|json| serde_json::from_str(&json).map_err(|e| e.to_string()))
+      .unwrap_or_else(|e| fail(format!("cannot read {}: {e}", path.display())));
+    old.diff(&Snapshot::new(report.clone()))
+  });
+  if let Some(path) = &args.save {
+    let json = serde_json::to_string_pretty(&Snapshot::new(report.clone()))
+      .unwrap_or_else(|e| fail(e.to_string()));
+    fs::write(path, json).unwrap_or_else(|e| fail(format!("cannot write {}: {e}", path.display())));
+  }
+
+  let rows: Vec<_> = rows(&report)
+    .into_iter()
+    .filter(|row| args.family.map_or(true, |family| family == row.family))
+    .collect();
+  let missing: Vec<_> = args
+    .require
+    .iter()
+    .filter(|capability| !capability.available(&report))
+    .map(Capability::as_str)
+    .collect();
+
+  if args.json {
+    println!("{:#}", to_json(report, &missing, diff));
+  } else {
+    print_table(&report, &rows, args.family != Some(Family::V4));
+    if let Some(diff) = diff {
+      println!();
+      if diff.is_empty() {
+        println!("changes: none");
+      } else {
+        println!("changes:");
+        for change in diff.changes() {
+          println!("  {change}");
+        }
+      }
+    }
+  }
+
+  if missing.is_empty() {
+    ExitCode::SUCCESS
+  } else {
+    eprintln!("missing required capabilities: {}", missing.join(", "));
+    ExitCode::from(EXIT_MISSING)
+  }
+}
+
+/// Exits like a usage error.
+fn fail(msg: String) -> ! {
+  Args::command().error(ErrorKind::Io, msg).exit()
+}
+
+fn print_table(report: &ProbeReport, rows: &[Row], v6: bool) {
+  let cell = |res: Option<Result<(), ProbeError>>| match res {
+    Some(Ok(())) => "yes",
+    Some(Err(_)) => "no",
+    None => "-",
+  };
+
+  println!("{:<18} {:<5} {:<5} connect", "family", "tcp", "udp");
+  for row in rows {
+    println!(
+      "{:<18} {:<5} {:<5} {}",
+      row.name,
+      cell(row.tcp),
+      cell(row.udp),
+      cell(row.connect)
+    );
+  }
+
+  if v6 && (report.dual_stack_listener().is_some() || report.v6only().is_some()) {
+    println!();
+    if let Some(res) = report.dual_stack_listener() {
+      match res {
+        Ok(peer) if report.dual_stack_listener_works() => {
+          println!(
+            "dual-stack listener: yes, IPv4 client seen as {}",
+            peer.ip()
+          );
+        }
+        Ok(peer) => println!("dual-stack listener: no, IPv4 client seen as {}", peer.ip()),
+        Err(e) => println!("dual-stack listener: no, {e}"),
+      }
+    }
+
+    if let Some(v6only) = report.v6only() {
+      let bindv6only = v6only
+        .bindv6only()
+        .map(|b| format!(" (net.ipv6.bindv6only = {})", u8::from(b)))
+        .unwrap_or_default();
+      match v6only.default_v6only() {
+        Ok(default) => println!("default IPV6_V6ONLY: {default}{bindv6only}"),
+        Err(e) => println!("default IPV6_V6ONLY: unknown, {e}"),
+      }
+      if v6only.dual_stack_rejected() {
+        println!("disabling IPV6_V6ONLY is rejected, IPv6 sockets are never dual-stack");
+      }
+    }
+  }
+
+  let errors: Vec<_> = rows
+    .iter()
+    .flat_map(|row| {
+      [("tcp", row.tcp), ("udp", row.udp), ("connect", row.connect)]
+        .into_iter()
+        .filter_map(move |(check, res)| Some((row.name, check, res?.err()?)))
+    })
+    .collect();
+  if !errors.is_empty() {
+    println!();
+    for (name, check, e) in errors {
+      println!("{name} {check}: {e}");
+    }
+  }
+
+  let overrides = report.overrides();
+  if !overrides.is_empty() {
+    println!();
+    println!("overrides: {overrides}");
+  }
+}
+
+/// Returns the serialized snapshot of the report, extended with the
+/// missing capabilities and the diff.
+fn to_json(report: ProbeReport, missing: &[&str], diff: Option<ProbeDiff>) -> Value {
+  let mut json =
+    serde_json::to_value(Snapshot::new(report)).unwrap_or_else(|e| fail(e.to_string()));
+  if let Value::Object(fields) = &mut json {
+    fields.insert("missing".to_owned(), missing.into());
+    fields.insert(
+      "diff".to_owned(),
+      serde_json::to_value(diff).unwrap_or_else(|e| fail(e.to_string())),
+    );
+  }
+  json
+}
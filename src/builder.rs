@@ -0,0 +1,521 @@
+use core::time::Duration;
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV4, SocketAddrV6};
+
+use rustix::net::{ipproto, AddressFamily, Protocol, SocketType};
+
+use super::{
+  connectivity, v6only, Overrides, Probe, ProbeError, ProbeReport, RustixBackend, SocketBackend,
+  Step, TransportReport, DEFAULT_TIMEOUT,
+};
+
+/// A builder to configure which checks are run and how.
+///
+/// Unlike [`probe`](crate::probe), the results are never cached.
+///
+/// ```rust
+/// use iprobe::ProbeBuilder;
+/// use std::{net::Ipv6Addr, time::Duration};
+///
+/// let report = ProbeBuilder::new()
+///   .with_udp(false)
+///   .with_ipv6_addr(Ipv6Addr::LOCALHOST)
+///   .with_deep(true)
+///   .with_timeout(Duration::from_millis(500))
+///   .report();
+/// println!("{:?}", report.connectivity());
+/// ```
+///
+/// The socket checks go through a [`SocketBackend`], which can be replaced
+/// with a [`MockBackend`](crate::MockBackend) in tests, see
+/// [`ProbeBuilder::with_backend`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct ProbeBuilder<B = RustixBackend> {
+  tcp: bool,
+  udp: bool,
+  ipv4_addr: Option<Ipv4Addr>,
+  ipv6_addr: Ipv6Addr,
+  ipv4_mapped_ipv6_addr: Ipv4Addr,
+  deep: bool,
+  dual_stack_listener: bool,
+  v6only: bool,
+  sysctls: bool,
+  timeout: Duration,
+  backend: B,
+}
+
+impl Default for ProbeBuilder {
+  #[inline]
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl ProbeBuilder {
+  /// Creates a builder which runs the same checks as [`probe`](crate::probe).
+  #[inline]
+  pub const fn new() -> Self {
+    Self {
+      tcp: true,
+      udp: true,
+      ipv4_addr: None,
+      ipv6_addr: Ipv6Addr::LOCALHOST,
+      ipv4_mapped_ipv6_addr: Ipv4Addr::LOCALHOST,
+      deep: false,
+      dual_stack_listener: false,
+      v6only: true,
+      sysctls: true,
+      timeout: DEFAULT_TIMEOUT,
+      backend: RustixBackend,
+    }
+  }
+}
+
+impl<B: SocketBackend> ProbeBuilder<B> {
+  /// Sets the backend the socket checks go through.
+  ///
+  /// The IPv6 sysctls describe the host, they are not read unless
+  /// [`SocketBackend::SYSTEM`] is `true`.
+  ///
+  /// Default is [`RustixBackend`].
+  #[inline]
+  pub fn with_backend<C: SocketBackend>(self, backend: C) -> ProbeBuilder<C> {
+    ProbeBuilder {
+      tcp: self.tcp,
+      udp: self.udp,
+      ipv4_addr: self.ipv4_addr,
+      ipv6_addr: self.ipv6_addr,
+      ipv4_mapped_ipv6_addr: self.ipv4_mapped_ipv6_addr,
+      deep: self.deep,
+      dual_stack_listener: self.dual_stack_listener,
+      v6only: self.v6only,
+      sysctls: self.sysctls,
+      timeout: self.timeout,
+      backend,
+    }
+  }
+
+  /// Returns the backend the socket checks go through.
+  #[inline]
+  pub const fn backend(&self) -> &B {
+    &self.backend
+  }
+
+  /// Sets whether TCP stream sockets are checked.
+  ///
+  /// Default is `true`.
+  #[inline]
+  pub const fn with_tcp(mut self, tcp: bool) -> Self {
+    self.tcp = tcp;
+    self
+  }
+
+  /// Sets whether UDP datagram sockets are checked.
+  ///
+  /// Default is `true`.
+  #[inline]
+  pub const fn with_udp(mut self, udp: bool) -> Self {
+    self.udp = udp;
+    self
+  }
+
+  /// Sets the address the IPv4 check binds to, e.g. the address of a
+  /// specific interface.
+  ///
+  /// By default, the IPv4 check only creates a socket without binding it,
+  /// and the deep connectivity check uses `127.0.0.1`.
+  #[inline]
+  pub const fn with_ipv4_addr(mut self, addr: Ipv4Addr) -> Self {
+    self.ipv4_addr = Some(addr);
+    self
+  }
+
+  /// Sets the address the IPv6 check binds to, e.g. the address of a
+  /// specific interface.
+  ///
+  /// Default is `::1`.
+  #[inline]
+  pub const fn with_ipv6_addr(mut self, addr: Ipv6Addr) -> Self {
+    self.ipv6_addr = addr;
+    self
+  }
+
+  /// Sets the IPv4 address whose IPv4-mapped IPv6 form the IPv4-mapped IPv6
+  /// check binds to.
+  ///
+  /// Default is `127.0.0.1`, which is bound as `::ffff:127.0.0.1`.
+  #[inline]
+  pub const fn with_ipv4_mapped_ipv6_addr(mut self, addr: Ipv4Addr) -> Self {
+    self.ipv4_mapped_ipv6_addr = addr;
+    self
+  }
+
+  /// Sets whether end-to-end connectivity is checked, by connecting to a TCP
+  /// listener on each probe address and exchanging a byte.
+  ///
+  /// The results are available through [`ProbeReport::connectivity`].
+  ///
+  /// Default is `false`.
+  #[inline]
+  pub const fn with_deep(mut self, deep: bool) -> Self {
+    self.deep = deep;
+    self
+  }
+
+  /// Sets whether the dual-stack listener check is run, see
+  /// [`dual_stack_listener_works`](crate::dual_stack_listener_works).
+  ///
+  /// The result is available through [`ProbeReport::dual_stack_listener`].
+  ///
+  /// Default is `false`.
+  #[inline]
+  pub const fn with_dual_stack_listener(mut self, dual_stack_listener: bool) -> Self {
+    self.dual_stack_listener = dual_stack_listener;
+    self
+  }
+
+  /// Sets whether the default `IPV6_V6ONLY` behaviour of new IPv6 sockets
+  /// is checked, see [`try_v6only`](crate::try_v6only).
+  ///
+  /// The result is available through [`ProbeReport::v6only`].
+  ///
+  /// Default is `true`.
+  #[inline]
+  pub const fn with_v6only(mut self, v6only: bool) -> Self {
+    self.v6only = v6only;
+    self
+  }
+
+  /// Sets whether the IPv6 sysctls are read on Linux, which takes a file
+  /// read per interface.
+  ///
+  /// The result is available through `ProbeReport::ipv6_sysctls`.
+  ///
+  /// Default is `true`.
+  #[inline]
+  pub const fn with_sysctls(mut self, sysctls: bool) -> Self {
+    self.sysctls = sysctls;
+    self
+  }
+
+  /// Sets the time after which a single connectivity check gives up.
+  ///
+  /// Default is 1 second.
+  #[inline]
+  pub const fn with_timeout(mut self, timeout: Duration) -> Self {
+    self.timeout = timeout;
+    self
+  }
+
+  /// Runs the configured checks and summarizes the capabilities.
+  ///
+  /// Transports which are not checked are reported as unsupported.
+  #[inline]
+  pub fn probe(&self) -> Probe {
+    self.report().probe()
+  }
+
+  /// Runs the configured checks.
+  pub fn report(&self) -> ProbeReport {
+    #[cfg(windows)]
+    let _ = rustix::net::wsa_startup();
+
+    #[cfg(target_os = "linux")]
+    let ipv6_sysctls = if self.sysctls && B::SYSTEM {
+      crate::Ipv6Sysctls::read()
+    } else {
+      None
+    };
+    let bindv6only = || {
+      #[cfg(target_os = "linux")]
+      if let Some(sysctls) = &ipv6_sysctls {
+        return sysctls.bindv6only();
+      }
+      if B::SYSTEM {
+        v6only::bindv6only()
+      } else {
+        None
+      }
+    };
+
+    let report = ProbeReport {
+      tcp: self
+        .tcp
+        .then(|| self.probe_transport(SocketType::STREAM, ipproto::TCP)),
+      udp: self
+        .udp
+        .then(|| self.probe_transport(SocketType::DGRAM, ipproto::UDP)),
+      connectivity: self.deep.then(|| {
+        connectivity::check_connectivity(
+          &self.backend,
+          self.ipv4_addr.unwrap_or(Ipv4Addr::LOCALHOST),
+          self.ipv6_addr,
+          self.ipv4_mapped_ipv6_addr,
+          self.timeout,
+        )
+      }),
+      dual_stack_listener: self
+        .dual_stack_listener
+        .then(|| connectivity::dual_stack(&self.backend, self.timeout)),
+      v6only: self
+        .v6only
+        .then(|| v6only::check(&self.backend, bindv6only())),
+      #[cfg(target_os = "linux")]
+      ipv6_sysctls,
+      overrides: if B::SYSTEM {
+        crate::overrides()
+      } else {
+        Overrides::NONE
+      },
+    };
+
+    #[cfg(windows)]
+    let _ = rustix::net::wsa_cleanup();
+
+    report
+  }
+
+  /// Runs the configured checks without blocking the tokio runtime.
+  ///
+  /// The socket checks run on the blocking thread pool, and the
+  /// connectivity checks wait for their sockets with tokio. Must be called
+  /// within a tokio runtime.
+  ///
+  /// The connectivity checks of tokio perform the system calls themselves,
+  /// so with a backend whose [`SocketBackend::SYSTEM`] is `true` they do
+  /// not go through it. Backends of other kinds run every check on the
+  /// blocking thread pool.
+  #[cfg(feature = "tokio")]
+  #[cfg_attr(docsrs, doc(cfg(feature = "tokio")))]
+  pub async fn report_async(&self) -> ProbeReport
+  where
+    B: Clone + Send + 'static,
+  {
+    let shallow = self
+      .clone()
+      .with_deep(self.deep && !B::SYSTEM)
+      .with_dual_stack_listener(self.dual_stack_listener && !B::SYSTEM);
+    let mut report = match tokio::task::spawn_blocking(move || shallow.report()).await {
+      Ok(report) => report,
+      Err(e) => std::panic::resume_unwind(e.into_panic()),
+    };
+
+    #[cfg(windows)]
+    let _ = rustix::net::wsa_startup();
+
+    if self.deep && B::SYSTEM {
+      report.connectivity = Some(
+        connectivity::r#async::check_connectivity(
+          self.ipv4_addr.unwrap_or(Ipv4Addr::LOCALHOST),
+          self.ipv6_addr,
+          self.ipv4_mapped_ipv6_addr,
+          self.timeout,
+        )
+        .await,
+      );
+    }
+    if self.dual_stack_listener && B::SYSTEM {
+      report.dual_stack_listener = Some(connectivity::r#async::dual_stack(self.timeout).await);
+    }
+
+    #[cfg(windows)]
+    let _ = rustix::net::wsa_cleanup();
+
+    report
+  }
+
+  fn probe_transport(&self, ty: SocketType, protocol: Protocol) -> TransportReport {
+    let ipv4_addr = self
+      .ipv4_addr
+      .map(|addr| SocketAddr::V4(SocketAddrV4::new(addr, 0)));
+    let ipv6_addr = SocketAddr::V6(SocketAddrV6::new(self.ipv6_addr, 0, 0, 0));
+    let ipv4_mapped_ipv6_addr = SocketAddr::V6(SocketAddrV6::new(
+      self.ipv4_mapped_ipv6_addr.to_ipv6_mapped(),
+      0,
+      0,
+      0,
+    ));
+
+    // Check IPv4 support
+    let (ipv4, _) = check(
+      &self.backend,
+      AddressFamily::INET,
+      ty,
+      protocol,
+      None,
+      ipv4_addr,
+    );
+    // Probe IPv6 and IPv4-mapped IPv6
+    let (ipv6, ipv6_v6only) = check(
+      &self.backend,
+      AddressFamily::INET6,
+      ty,
+      protocol,
+      Some(true),
+      Some(ipv6_addr),
+    );
+    let (ipv4_mapped_ipv6, ipv4_mapped_ipv6_v6only) = check(
+      &self.backend,
+      AddressFamily::INET6,
+      ty,
+      protocol,
+      Some(false),
+      Some(ipv4_mapped_ipv6_addr),
+    );
+
+    TransportReport {
+      ipv4,
+      ipv6,
+      ipv4_mapped_ipv6,
+      ipv6_v6only,
+      ipv4_mapped_ipv6_v6only,
+    }
+  }
+}
+
+/// Creates a socket of the given family and type, optionally sets
+/// `IPV6_V6ONLY` and binds it to `addr`.
+///
+/// Like the bind, the result of setting `IPV6_V6ONLY` is returned
+/// separately, the bind alone decides whether the check succeeds.
+fn check<B: SocketBackend>(
+  backend: &B,
+  family: AddressFamily,
+  ty: SocketType,
+  protocol: Protocol,
+  v6_only: Option<bool>,
+  addr: Option<SocketAddr>,
+) -> (Result<(), ProbeError>, Option<ProbeError>) {
+  let sock = match backend.socket(family, ty, Some(protocol)) {
+    Ok(sock) => sock,
+    Err(e) => return (Err(ProbeError::new(Step::Socket, e, None)), None),
+  };
+
+  let v6only = v6_only.and_then(|v6_only| {
+    backend
+      .set_ipv6_v6only(&sock, v6_only)
+      .err()
+      .map(|e| ProbeError::new(Step::SetIpv6V6Only, e, None))
+  });
+
+  let bind = match addr {
+    Some(addr) => backend
+      .bind(&sock, &addr)
+      .map_err(|e| ProbeError::new(Step::Bind, e, Some(addr))),
+    None => Ok(()),
+  };
+  (bind, v6only)
+}
+
+#[test]
+fn test_builder() {
+  let report = ProbeBuilder::new().with_udp(false).report();
+  assert!(report.tcp().is_some());
+  assert!(report.udp().is_none());
+  assert!(report.connectivity().is_none());
+  assert!(report.dual_stack_listener().is_none());
+  assert!(report.v6only().is_some());
+  assert!(!report.probe().udp().ipv4());
+
+  let report = ProbeBuilder::new().with_sysctls(false).report();
+  #[cfg(target_os = "linux")]
+  assert!(report.ipv6_sysctls().is_none());
+  // `bindv6only` is read on its own.
+  assert_eq!(
+    report.v6only().unwrap().bindv6only(),
+    super::v6only::bindv6only()
+  );
+
+  // TEST-NET-1 is never assigned to the host
+  let report = ProbeBuilder::new()
+    .with_ipv4_addr(Ipv4Addr::new(192, 0, 2, 1))
+    .report();
+  let err = report.ipv4().unwrap().unwrap_err();
+  assert_eq!(err.step(), Step::Bind);
+}
+
+#[test]
+fn test_builder_backend() {
+  use rustix::io::Errno;
+
+  use super::MockBackend;
+
+  let report = ProbeBuilder::new()
+    .with_v6only(false)
+    .with_backend(MockBackend::new().with_v6only_error(false, Step::Bind, Errno::ADDRNOTAVAIL))
+    .report();
+  let tcp = report.tcp().unwrap().probe();
+  assert!(tcp.ipv4() && tcp.ipv6() && !tcp.ipv4_mapped_ipv6());
+  let err = report.udp().unwrap().ipv4_mapped_ipv6().unwrap_err();
+  assert_eq!(err.step(), Step::Bind);
+  assert_eq!(err.errno(), Errno::ADDRNOTAVAIL);
+
+  // A failed IPV6_V6ONLY does not fail the check if the bind works.
+  let report = ProbeBuilder::new()
+    .with_udp(false)
+    .with_backend(MockBackend::new().with_v6only_error(
+      true,
+      Step::SetIpv6V6Only,
+      Errno::NOPROTOOPT,
+    ))
+    .report();
+  let tcp = report.tcp().unwrap();
+  assert_eq!(tcp.ipv6(), Ok(()));
+  assert_eq!(tcp.ipv6_v6only_error().unwrap().errno(), Errno::NOPROTOOPT);
+  assert_eq!(tcp.ipv4_mapped_ipv6_v6only_error(), None);
+
+  let report = ProbeBuilder::new()
+    .with_deep(true)
+    .with_dual_stack_listener(true)
+    .with_backend(MockBackend::new().with_default_v6only(true).with_error(
+      AddressFamily::INET,
+      Step::Socket,
+      Errno::AFNOSUPPORT,
+    ))
+    .report();
+  assert!(!report.tcp().unwrap().probe().ipv4());
+  assert!(report.tcp().unwrap().probe().ipv6());
+  assert_eq!(report.v6only().unwrap().default_v6only(), Ok(true));
+  let connectivity = report.connectivity().unwrap();
+  assert_eq!(connectivity.ipv4().unwrap_err().step(), Step::Socket);
+  assert_eq!(connectivity.ipv6(), Ok(()));
+  assert_eq!(
+    report.dual_stack_listener().unwrap().unwrap_err().step(),
+    Step::Socket
+  );
+  // The sysctls describe the host.
+  #[cfg(target_os = "linux")]
+  assert!(report.ipv6_sysctls().is_none());
+
+  let report = ProbeBuilder::new()
+    .with_deep(true)
+    .with_dual_stack_listener(true)
+    .with_backend(MockBackend::new().with_error(
+      AddressFamily::INET6,
+      Step::Connect,
+      Errno::NETUNREACH,
+    ))
+    .report();
+  let connectivity = report.connectivity().unwrap();
+  assert_eq!(connectivity.ipv4(), Ok(()));
+  let err = connectivity.ipv6().unwrap_err();
+  assert_eq!(
+    (err.step(), err.errno()),
+    (Step::Connect, Errno::NETUNREACH)
+  );
+  assert_eq!(err.address(), Some("[::1]:49152".parse().unwrap()));
+  assert!(report.dual_stack_listener_works());
+
+  let report = ProbeBuilder::new()
+    .with_udp(false)
+    .with_deep(true)
+    .with_dual_stack_listener(true)
+    .with_backend(MockBackend::new().with_v6only_error(false, Step::Recv, Errno::CONNRESET))
+    .report();
+  let connectivity = report.connectivity().unwrap();
+  assert!(connectivity.ipv4().is_ok() && connectivity.ipv6().is_ok());
+  assert_eq!(
+    connectivity.ipv4_mapped_ipv6().unwrap_err().step(),
+    Step::Recv
+  );
+  assert!(!report.dual_stack_listener_works());
+}
@@ -0,0 +1,222 @@
+use core::fmt;
+
+use super::{Probe, TransportProbe};
+
+bitflags::bitflags! {
+  /// A set of capabilities, e.g. the ones a service requires.
+  ///
+  /// The unqualified flags are the capabilities of TCP stream sockets, like
+  /// the top level accessors of [`Probe`].
+  ///
+  /// ```rust
+  /// use iprobe::{probe, Capabilities};
+  ///
+  /// let missing = probe().missing(Capabilities::IPV4 | Capabilities::UDP_IPV4);
+  /// if !missing.is_empty() {
+  ///   eprintln!("missing required capabilities: {missing}");
+  /// }
+  /// ```
+  #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+  pub struct Capabilities: u32 {
+    /// TCP over IPv4, see [`Probe::ipv4`].
+    const IPV4 = 1;
+    /// TCP over IPv6, see [`Probe::ipv6`].
+    const IPV6 = 1 << 1;
+    /// TCP over IPv4-mapped IPv6, see [`Probe::ipv4_mapped_ipv6`].
+    const IPV4_MAPPED = 1 << 2;
+    /// UDP over IPv4.
+    const UDP_IPV4 = 1 << 3;
+    /// UDP over IPv6.
+    const UDP_IPV6 = 1 << 4;
+    /// UDP over IPv4-mapped IPv6.
+    const UDP_IPV4_MAPPED = 1 << 5;
+  }
+}
+
+impl fmt::Display for Capabilities {
+  /// Writes the capabilities like [`Probe`] does, e.g. `IPv4, UDP IPv4`,
+  /// or `none`.
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    self.write_labels(f)
+  }
+}
+
+impl Capabilities {
+  /// The single capabilities, in display order.
+  pub(crate) const SINGLE: [Self; 6] = [
+    Self::IPV4,
+    Self::IPV6,
+    Self::IPV4_MAPPED,
+    Self::UDP_IPV4,
+    Self::UDP_IPV6,
+    Self::UDP_IPV4_MAPPED,
+  ];
+
+  /// Returns the human readable name of a single capability, e.g.
+  /// `UDP IPv4-mapped IPv6`.
+  pub(crate) fn label(self) -> &'static str {
+    match self {
+      Self::IPV4 => "IPv4",
+      Self::IPV6 => "IPv6",
+      Self::IPV4_MAPPED => "IPv4-mapped IPv6",
+      Self::UDP_IPV4 => "UDP IPv4",
+      Self::UDP_IPV6 => "UDP IPv6",
+      Self::UDP_IPV4_MAPPED => "UDP IPv4-mapped IPv6",
+      _ => "unknown",
+    }
+  }
+
+  /// Writes the labels of the set, or `none`.
+  fn write_labels(self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    if self.is_empty() {
+      return f.write_str("none");
+    }
+
+    let flags = Self::SINGLE.iter().filter(|flag| self.contains(**flag));
+    for (i, flag) in flags.enumerate() {
+      if i > 0 {
+        f.write_str(", ")?;
+      }
+      f.write_str(flag.label())?;
+    }
+    Ok(())
+  }
+}
+
+impl Probe {
+  /// Creates the capabilities of a host from the ones of each transport.
+  #[inline]
+  pub const fn new(tcp: TransportProbe, udp: TransportProbe) -> Self {
+    Self { tcp, udp }
+  }
+
+  /// Creates the capabilities of a host which supports exactly `caps`.
+  #[inline]
+  pub const fn from_capabilities(caps: Capabilities) -> Self {
+    Self {
+      tcp: TransportProbe::new(
+        caps.contains(Capabilities::IPV4),
+        caps.contains(Capabilities::IPV6),
+        caps.contains(Capabilities::IPV4_MAPPED),
+      ),
+      udp: TransportProbe::new(
+        caps.contains(Capabilities::UDP_IPV4),
+        caps.contains(Capabilities::UDP_IPV6),
+        caps.contains(Capabilities::UDP_IPV4_MAPPED),
+      ),
+    }
+  }
+
+  /// Returns the supported capabilities as a set.
+  #[inline]
+  pub const fn capabilities(&self) -> Capabilities {
+    const fn flag(supported: bool, flag: Capabilities) -> Capabilities {
+      if supported {
+        flag
+      } else {
+        Capabilities::empty()
+      }
+    }
+
+    flag(self.tcp.ipv4, Capabilities::IPV4)
+      .union(flag(self.tcp.ipv6, Capabilities::IPV6))
+      .union(flag(self.tcp.ipv4_mapped_ipv6, Capabilities::IPV4_MAPPED))
+      .union(flag(self.udp.ipv4, Capabilities::UDP_IPV4))
+      .union(flag(self.udp.ipv6, Capabilities::UDP_IPV6))
+      .union(flag(
+        self.udp.ipv4_mapped_ipv6,
+        Capabilities::UDP_IPV4_MAPPED,
+      ))
+  }
+
+  /// Returns `true` if every capability in `required` is supported.
+  #[inline]
+  pub const fn satisfies(&self, required: Capabilities) -> bool {
+    self.capabilities().contains(required)
+  }
+
+  /// Returns the capabilities in `required` which are not supported.
+  #[inline]
+  pub const fn missing(&self, required: Capabilities) -> Capabilities {
+    required.difference(self.capabilities())
+  }
+}
+
+impl TransportProbe {
+  /// Creates the capabilities of a transport.
+  #[inline]
+  pub const fn new(ipv4: bool, ipv6: bool, ipv4_mapped_ipv6: bool) -> Self {
+    Self {
+      ipv4,
+      ipv6,
+      ipv4_mapped_ipv6,
+    }
+  }
+}
+
+impl fmt::Display for Probe {
+  /// Writes the supported capabilities, e.g. `IPv4, IPv6, UDP IPv4`, or
+  /// `none`.
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    self.capabilities().write_labels(f)
+  }
+}
+
+impl fmt::Display for TransportProbe {
+  /// Writes the supported families, e.g. `IPv4, IPv4-mapped IPv6`, or
+  /// `none`.
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let none = TransportProbe::new(false, false, false);
+    Probe::new(*self, none).capabilities().write_labels(f)
+  }
+}
+
+impl From<Capabilities> for Probe {
+  #[inline]
+  fn from(caps: Capabilities) -> Self {
+    Self::from_capabilities(caps)
+  }
+}
+
+impl From<Probe> for Capabilities {
+  #[inline]
+  fn from(probe: Probe) -> Self {
+    probe.capabilities()
+  }
+}
+
+#[test]
+fn test_capabilities() {
+  let ipv4_only = Probe::new(
+    TransportProbe::new(true, false, false),
+    TransportProbe::new(true, false, false),
+  );
+  assert_eq!(
+    ipv4_only.capabilities(),
+    Capabilities::IPV4 | Capabilities::UDP_IPV4
+  );
+  assert_eq!(
+    Probe::from_capabilities(ipv4_only.capabilities()),
+    ipv4_only
+  );
+
+  assert!(ipv4_only.satisfies(Capabilities::IPV4));
+  assert!(ipv4_only.satisfies(Capabilities::empty()));
+  let required = Capabilities::IPV4 | Capabilities::IPV6 | Capabilities::UDP_IPV4_MAPPED;
+  assert!(!ipv4_only.satisfies(required));
+  assert_eq!(
+    ipv4_only.missing(required),
+    Capabilities::IPV6 | Capabilities::UDP_IPV4_MAPPED
+  );
+  assert_eq!(
+    ipv4_only.missing(required).to_string(),
+    "IPv6, UDP IPv4-mapped IPv6"
+  );
+  assert_eq!(Capabilities::empty().to_string(), "none");
+
+  assert!(Probe::from(Capabilities::all()).satisfies(required));
+
+  assert_eq!(ipv4_only.to_string(), "IPv4, UDP IPv4");
+  assert_eq!(ipv4_only.udp().to_string(), "IPv4");
+  assert_eq!(Probe::from(Capabilities::empty()).to_string(), "none");
+}
@@ -0,0 +1,402 @@
+use core::{fmt, time::Duration};
+use std::{
+  net::{Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV4, SocketAddrV6},
+  time::Instant,
+};
+
+use rustix::{
+  io::Errno,
+  net::{ipproto, AddressFamily, SocketType},
+};
+
+use super::{report::Lines, ProbeError, SocketBackend, Step, TransportProbe};
+
+const PING: u8 = 0x2a;
+
+const IPV4_LOCALHOST_ADDR: SocketAddr = SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::LOCALHOST, 0));
+
+const IPV6_UNSPECIFIED_ADDR: SocketAddr =
+  SocketAddr::V6(SocketAddrV6::new(Ipv6Addr::UNSPECIFIED, 0, 0, 0));
+
+/// The results of the end-to-end loopback connectivity checks, which
+/// connect to a listener over loopback and exchange a byte.
+///
+/// See [`try_probe_deep`](crate::try_probe_deep).
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ConnectivityReport {
+  #[cfg_attr(feature = "serde", serde(with = "crate::snapshot::check"))]
+  ipv4: Result<(), ProbeError>,
+  #[cfg_attr(feature = "serde", serde(with = "crate::snapshot::check"))]
+  ipv6: Result<(), ProbeError>,
+  #[cfg_attr(feature = "serde", serde(with = "crate::snapshot::check"))]
+  ipv4_mapped_ipv6: Result<(), ProbeError>,
+}
+
+impl ConnectivityReport {
+  /// Returns the result of the IPv4 check, over `127.0.0.1` by default.
+  #[inline]
+  pub const fn ipv4(&self) -> Result<(), ProbeError> {
+    self.ipv4
+  }
+
+  /// Returns the result of the IPv6 check, over `::1` by default.
+  #[inline]
+  pub const fn ipv6(&self) -> Result<(), ProbeError> {
+    self.ipv6
+  }
+
+  /// Returns the result of the IPv4-mapped IPv6 check, over
+  /// `::ffff:127.0.0.1` by default.
+  #[inline]
+  pub const fn ipv4_mapped_ipv6(&self) -> Result<(), ProbeError> {
+    self.ipv4_mapped_ipv6
+  }
+
+  /// Returns the capabilities summarized by this report.
+  #[inline]
+  pub const fn probe(&self) -> TransportProbe {
+    TransportProbe {
+      ipv4: self.ipv4.is_ok(),
+      ipv6: self.ipv6.is_ok(),
+      ipv4_mapped_ipv6: self.ipv4_mapped_ipv6.is_ok(),
+    }
+  }
+}
+
+impl ConnectivityReport {
+  pub(crate) fn write_checks(&self, lines: &mut Lines<'_, '_>, suffix: &str) -> fmt::Result {
+    lines.check(format_args!("IPv4{suffix}"), self.ipv4)?;
+    lines.check(format_args!("IPv6{suffix}"), self.ipv6)?;
+    lines.check(
+      format_args!("IPv4-mapped IPv6{suffix}"),
+      self.ipv4_mapped_ipv6,
+    )
+  }
+}
+
+impl fmt::Display for ConnectivityReport {
+  /// Writes one line per check, e.g. `IPv4: available`.
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    self.write_checks(&mut Lines::new(f), "")
+  }
+}
+
+pub(crate) fn check_connectivity<B: SocketBackend>(
+  backend: &B,
+  ipv4_addr: Ipv4Addr,
+  ipv6_addr: Ipv6Addr,
+  ipv4_mapped_ipv6_addr: Ipv4Addr,
+  timeout: Duration,
+) -> ConnectivityReport {
+  // Each check listens on its address, connects to itself, sends a byte,
+  // accepts the connection and receives the byte.
+  let [ipv4, ipv6, ipv4_mapped_ipv6] = endpoints(ipv4_addr, ipv6_addr, ipv4_mapped_ipv6_addr)
+    .map(|endpoint| exchange(backend, endpoint, endpoint, timeout).map(|_| ()));
+  ConnectivityReport {
+    ipv4,
+    ipv6,
+    ipv4_mapped_ipv6,
+  }
+}
+
+/// Returns the endpoints of the IPv4, IPv6 and IPv4-mapped IPv6 checks.
+fn endpoints(
+  ipv4_addr: Ipv4Addr,
+  ipv6_addr: Ipv6Addr,
+  ipv4_mapped_ipv6_addr: Ipv4Addr,
+) -> [Endpoint; 3] {
+  [
+    Endpoint {
+      family: AddressFamily::INET,
+      v6_only: None,
+      addr: SocketAddr::V4(SocketAddrV4::new(ipv4_addr, 0)),
+    },
+    Endpoint {
+      family: AddressFamily::INET6,
+      v6_only: Some(true),
+      addr: SocketAddr::V6(SocketAddrV6::new(ipv6_addr, 0, 0, 0)),
+    },
+    Endpoint {
+      family: AddressFamily::INET6,
+      v6_only: Some(false),
+      addr: SocketAddr::V6(SocketAddrV6::new(
+        ipv4_mapped_ipv6_addr.to_ipv6_mapped(),
+        0,
+        0,
+        0,
+      )),
+    },
+  ]
+}
+
+/// Binds `[::]:0` with `IPV6_V6ONLY` disabled, connects to it from an IPv4
+/// socket via `127.0.0.1` and exchanges a byte, all within `timeout`.
+///
+/// Returns the address of the client as seen by the listener.
+pub(crate) fn dual_stack<B: SocketBackend>(
+  backend: &B,
+  timeout: Duration,
+) -> Result<SocketAddr, ProbeError> {
+  exchange(backend, DUAL_STACK_LISTENER, DUAL_STACK_CLIENT, timeout)
+}
+
+const DUAL_STACK_LISTENER: Endpoint = Endpoint {
+  family: AddressFamily::INET6,
+  v6_only: Some(false),
+  addr: IPV6_UNSPECIFIED_ADDR,
+};
+
+const DUAL_STACK_CLIENT: Endpoint = Endpoint {
+  family: AddressFamily::INET,
+  v6_only: None,
+  addr: IPV4_LOCALHOST_ADDR,
+};
+
+/// A socket of a connectivity check.
+#[derive(Copy, Clone)]
+struct Endpoint {
+  family: AddressFamily,
+  v6_only: Option<bool>,
+  /// The address to bind for the listener, or the address to connect to
+  /// for the client, whose port is replaced with the one of the listener.
+  addr: SocketAddr,
+}
+
+/// Creates the listener and the client, connects them, sends a byte from
+/// the client and receives it on the accepted connection.
+///
+/// Returns the peer address of the accepted connection.
+fn exchange<B: SocketBackend>(
+  backend: &B,
+  listener: Endpoint,
+  client: Endpoint,
+  timeout: Duration,
+) -> Result<SocketAddr, ProbeError> {
+  let deadline = Instant::now() + timeout;
+  let err = |step, addr| move |e| ProbeError::new(step, e, Some(addr));
+
+  let (listener, port) = listen_on(backend, listener)?;
+
+  let addr = SocketAddr::new(client.addr.ip(), port);
+  let client = stream_socket(backend, client.family, client.v6_only)?;
+  backend
+    .connect(&client, &addr, deadline)
+    .map_err(err(Step::Connect, addr))?;
+  backend
+    .send(&client, &[PING])
+    .map_err(err(Step::Send, addr))?;
+
+  let (conn, peer) = backend
+    .accept(&listener, deadline)
+    .map_err(err(Step::Accept, addr))?;
+
+  let mut buf = [0; 1];
+  match backend.recv(&conn, &mut buf, deadline) {
+    Ok(1) => Ok(peer),
+    // The connection was closed before the byte arrived.
+    Ok(_) => Err(err(Step::Recv, addr)(Errno::CONNRESET)),
+    Err(e) => Err(err(Step::Recv, addr)(e)),
+  }
+}
+
+/// Creates a listener bound to the address of `endpoint`, returns it and
+/// its port.
+fn listen_on<B: SocketBackend>(
+  backend: &B,
+  endpoint: Endpoint,
+) -> Result<(B::Socket, u16), ProbeError> {
+  let addr = endpoint.addr;
+  let err = |step| move |e| ProbeError::new(step, e, Some(addr));
+
+  let listener = stream_socket(backend, endpoint.family, endpoint.v6_only)?;
+  backend.bind(&listener, &addr).map_err(err(Step::Bind))?;
+  let port = backend.listen(&listener).map_err(err(Step::Listen))?.port();
+  Ok((listener, port))
+}
+
+#[inline]
+pub(crate) fn is_ipv4_mapped(addr: SocketAddr) -> bool {
+  matches!(addr, SocketAddr::V6(addr) if addr.ip().to_ipv4_mapped().is_some())
+}
+
+fn stream_socket<B: SocketBackend>(
+  backend: &B,
+  family: AddressFamily,
+  v6_only: Option<bool>,
+) -> Result<B::Socket, ProbeError> {
+  let sock = backend
+    .socket(family, SocketType::STREAM, Some(ipproto::TCP))
+    .map_err(|e| ProbeError::new(Step::Socket, e, None))?;
+
+  if let Some(v6_only) = v6_only {
+    backend
+      .set_ipv6_v6only(&sock, v6_only)
+      .map_err(|e| ProbeError::new(Step::SetIpv6V6Only, e, None))?;
+  }
+
+  Ok(sock)
+}
+
+#[cfg(feature = "tokio")]
+pub(crate) mod r#async {
+  use core::future::Future;
+  use std::io;
+
+  use rustix::io::ioctl_fionbio;
+  use tokio::{
+    net::{TcpListener, TcpSocket, TcpStream},
+    time::timeout_at,
+  };
+
+  use super::*;
+  use crate::RustixBackend;
+
+  /// The asynchronous version of [`check_connectivity`](super::check_connectivity).
+  pub(crate) async fn check_connectivity(
+    ipv4_addr: Ipv4Addr,
+    ipv6_addr: Ipv6Addr,
+    ipv4_mapped_ipv6_addr: Ipv4Addr,
+    timeout: Duration,
+  ) -> ConnectivityReport {
+    let [ipv4, ipv6, ipv4_mapped_ipv6] = endpoints(ipv4_addr, ipv6_addr, ipv4_mapped_ipv6_addr);
+    ConnectivityReport {
+      ipv4: exchange(ipv4, ipv4, timeout).await.map(|_| ()),
+      ipv6: exchange(ipv6, ipv6, timeout).await.map(|_| ()),
+      ipv4_mapped_ipv6: exchange(ipv4_mapped_ipv6, ipv4_mapped_ipv6, timeout)
+        .await
+        .map(|_| ()),
+    }
+  }
+
+  /// The asynchronous version of [`dual_stack`](super::dual_stack).
+  pub(crate) async fn dual_stack(timeout: Duration) -> Result<SocketAddr, ProbeError> {
+    exchange(DUAL_STACK_LISTENER, DUAL_STACK_CLIENT, timeout).await
+  }
+
+  /// The asynchronous version of [`exchange`](super::exchange), which
+  /// waits for the sockets of a [`RustixBackend`] with tokio.
+  async fn exchange(
+    listener: Endpoint,
+    client: Endpoint,
+    timeout: Duration,
+  ) -> Result<SocketAddr, ProbeError> {
+    let deadline = Instant::now() + timeout;
+    let err = |step, addr| move |e: io::Error| ProbeError::new(step, errno(&e), Some(addr));
+
+    let listen_addr = listener.addr;
+    let (listener, port) = listen_on(&RustixBackend, listener)?;
+    let listener = std::net::TcpListener::from(listener);
+    let listener = listener
+      .set_nonblocking(true)
+      .and_then(|()| TcpListener::from_std(listener))
+      .map_err(err(Step::Listen, listen_addr))?;
+
+    let addr = SocketAddr::new(client.addr.ip(), port);
+    let client = stream_socket(&RustixBackend, client.family, client.v6_only)?;
+    ioctl_fionbio(&client, true).map_err(|e| ProbeError::new(Step::Connect, e, Some(addr)))?;
+    let client = TcpSocket::from_std_stream(std::net::TcpStream::from(client));
+    let client = within(deadline, client.connect(addr))
+      .await
+      .map_err(err(Step::Connect, addr))?;
+    send(&client, deadline)
+      .await
+      .map_err(err(Step::Send, addr))?;
+
+    let (conn, peer) = within(deadline, listener.accept())
+      .await
+      .map_err(err(Step::Accept, addr))?;
+    recv(&conn, deadline).await.map_err(err(Step::Recv, addr))?;
+    Ok(peer)
+  }
+
+  async fn send(stream: &TcpStream, deadline: Instant) -> io::Result<()> {
+    loop {
+      within(deadline, stream.writable()).await?;
+      match stream.try_write(&[PING]) {
+        Ok(_) => return Ok(()),
+        Err(e) if e.kind() == io::ErrorKind::WouldBlock => {}
+        Err(e) => return Err(e),
+      }
+    }
+  }
+
+  async fn recv(stream: &TcpStream, deadline: Instant) -> io::Result<()> {
+    let mut buf = [0; 1];
+    loop {
+      within(deadline, stream.readable()).await?;
+      match stream.try_read(&mut buf) {
+        Ok(1) if buf[0] == PING => return Ok(()),
+        // The connection was closed before the byte arrived.
+        Ok(_) => return Err(Errno::CONNRESET.into()),
+        Err(e) if e.kind() == io::ErrorKind::WouldBlock => {}
+        Err(e) => return Err(e),
+      }
+    }
+  }
+
+  /// Fails with `ETIMEDOUT` if `fut` does not complete before `deadline`.
+  async fn within<T>(deadline: Instant, fut: impl Future<Output = io::Result<T>>) -> io::Result<T> {
+    timeout_at(deadline.into(), fut)
+      .await
+      .unwrap_or_else(|_| Err(Errno::TIMEDOUT.into()))
+  }
+
+  fn errno(e: &io::Error) -> Errno {
+    Errno::from_io_error(e).unwrap_or(Errno::INVAL)
+  }
+}
+
+#[test]
+fn test_connectivity() {
+  let report = check_connectivity(
+    &crate::RustixBackend,
+    Ipv4Addr::LOCALHOST,
+    Ipv6Addr::LOCALHOST,
+    Ipv4Addr::LOCALHOST,
+    Duration::from_secs(1),
+  );
+  let bind = crate::ProbeBuilder::new()
+    .with_ipv4_addr(Ipv4Addr::LOCALHOST)
+    .with_udp(false)
+    .report()
+    .tcp()
+    .unwrap();
+  // A family which cannot be bound cannot be connected to either.
+  assert!(bind.ipv4().is_ok() || report.ipv4().is_err());
+  assert!(bind.ipv6().is_ok() || report.ipv6().is_err());
+  assert!(bind.ipv4_mapped_ipv6().is_ok() || report.ipv4_mapped_ipv6().is_err());
+
+  if crate::interfaces::loopback_up() {
+    assert_eq!(report.ipv4().is_ok(), bind.ipv4().is_ok());
+    assert_eq!(report.ipv6().is_ok(), bind.ipv6().is_ok());
+    assert_eq!(
+      report.ipv4_mapped_ipv6().is_ok(),
+      bind.ipv4_mapped_ipv6().is_ok()
+    );
+  }
+}
+
+#[cfg(all(test, feature = "tokio"))]
+#[tokio::test]
+async fn test_connectivity_async() {
+  let report = r#async::check_connectivity(
+    Ipv4Addr::LOCALHOST,
+    Ipv6Addr::LOCALHOST,
+    Ipv4Addr::LOCALHOST,
+    Duration::from_secs(1),
+  )
+  .await;
+  let blocking = check_connectivity(
+    &crate::RustixBackend,
+    Ipv4Addr::LOCALHOST,
+    Ipv6Addr::LOCALHOST,
+    Ipv4Addr::LOCALHOST,
+    Duration::from_secs(1),
+  );
+  assert_eq!(report.probe(), blocking.probe());
+  assert_eq!(
+    r#async::dual_stack(Duration::from_secs(1)).await.is_ok(),
+    dual_stack(&crate::RustixBackend, Duration::from_secs(1)).is_ok()
+  );
+}
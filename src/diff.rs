@@ -0,0 +1,205 @@
+use core::fmt;
+
+use super::{report::Lines, Capabilities, Probe, ProbeError, ProbeReport, TransportReport};
+
+/// The capabilities which changed between two probes, and why.
+///
+/// See [`diff`].
+///
+/// With the `serde` feature, a diff is serialized as the list of its
+/// [`changes`](ProbeDiff::changes).
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct ProbeDiff {
+  pub(crate) gained: Capabilities,
+  pub(crate) lost: Capabilities,
+  /// The reasons of the changes, indexed like [`Capabilities::SINGLE`].
+  pub(crate) reasons: [Option<ProbeError>; 6],
+}
+
+impl ProbeDiff {
+  /// Compares two probes, the changes have no reasons.
+  #[inline]
+  pub const fn between(old: Probe, new: Probe) -> Self {
+    let (old, new) = (old.capabilities(), new.capabilities());
+    Self {
+      gained: new.difference(old),
+      lost: old.difference(new),
+      reasons: [None; 6],
+    }
+  }
+
+  /// Returns `true` if no capability changed.
+  #[inline]
+  pub const fn is_empty(&self) -> bool {
+    self.gained.is_empty() && self.lost.is_empty()
+  }
+
+  /// Returns the capabilities which became available.
+  #[inline]
+  pub const fn gained(&self) -> Capabilities {
+    self.gained
+  }
+
+  /// Returns the capabilities which became unavailable.
+  #[inline]
+  pub const fn lost(&self) -> Capabilities {
+    self.lost
+  }
+
+  /// Returns an iterator over the changes, in the order of
+  /// [`Probe`]'s `Display`.
+  pub fn changes(&self) -> impl Iterator<Item = CapabilityChange> + '_ {
+    let changed = self.gained.union(self.lost);
+    Capabilities::SINGLE
+      .iter()
+      .zip(self.reasons)
+      .filter(move |(capability, _)| changed.contains(**capability))
+      .map(|(&capability, reason)| CapabilityChange {
+        capability,
+        available: self.gained.contains(capability),
+        reason,
+      })
+  }
+}
+
+impl fmt::Display for ProbeDiff {
+  /// Writes one line per change, or `no changes`.
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    if self.is_empty() {
+      return f.write_str("no changes");
+    }
+
+    let mut lines = Lines::new(f);
+    for change in self.changes() {
+      lines.line(format_args!("{change}"))?;
+    }
+    Ok(())
+  }
+}
+
+/// A capability which became available or unavailable, see
+/// [`ProbeDiff::changes`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CapabilityChange {
+  #[cfg_attr(feature = "serde", serde(with = "crate::snapshot::capability"))]
+  capability: Capabilities,
+  available: bool,
+  reason: Option<ProbeError>,
+}
+
+impl CapabilityChange {
+  /// Returns the capability, a single flag.
+  #[inline]
+  pub const fn capability(&self) -> Capabilities {
+    self.capability
+  }
+
+  /// Returns `true` if the capability became available, `false` if it
+  /// became unavailable.
+  #[inline]
+  pub const fn available(&self) -> bool {
+    self.available
+  }
+
+  /// Returns the failed check which made the capability unavailable, if
+  /// known.
+  #[inline]
+  pub const fn reason(&self) -> Option<ProbeError> {
+    self.reason
+  }
+}
+
+impl fmt::Display for CapabilityChange {
+  /// Writes the change, e.g.
+  /// `IPv6: available -> unavailable: bind ::1 failed (...)`.
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let label = self.capability.label();
+    if self.available {
+      write!(f, "{label}: unavailable -> available")
+    } else {
+      write!(f, "{label}: available -> unavailable")?;
+      match self.reason {
+        Some(reason) => write!(f, ": {reason}"),
+        None => Ok(()),
+      }
+    }
+  }
+}
+
+/// Compares two reports, the capabilities which became unavailable have
+/// the failed check of `new` as their reason.
+///
+/// ```rust
+/// use iprobe::{diff, try_probe};
+///
+/// let old = try_probe();
+/// let new = try_probe();
+/// for change in diff(&old, &new).changes() {
+///   println!("{change}");
+/// }
+/// ```
+pub fn diff(old: &ProbeReport, new: &ProbeReport) -> ProbeDiff {
+  let mut diff = ProbeDiff::between(old.probe(), new.probe());
+  let lost = diff.lost();
+  for (capability, reason) in Capabilities::SINGLE.iter().zip(&mut diff.reasons) {
+    if lost.contains(*capability) {
+      *reason = error(new, *capability);
+    }
+  }
+  diff
+}
+
+/// Returns the error of the check of a single capability.
+fn error(report: &ProbeReport, capability: Capabilities) -> Option<ProbeError> {
+  type Check = fn(&TransportReport) -> Result<(), ProbeError>;
+
+  let (transport, check): (_, Check) = match capability {
+    Capabilities::IPV4 => (report.tcp(), TransportReport::ipv4),
+    Capabilities::IPV6 => (report.tcp(), TransportReport::ipv6),
+    Capabilities::IPV4_MAPPED => (report.tcp(), TransportReport::ipv4_mapped_ipv6),
+    Capabilities::UDP_IPV4 => (report.udp(), TransportReport::ipv4),
+    Capabilities::UDP_IPV6 => (report.udp(), TransportReport::ipv6),
+    Capabilities::UDP_IPV4_MAPPED => (report.udp(), TransportReport::ipv4_mapped_ipv6),
+    _ => return None,
+  };
+  check(&transport?).err()
+}
+
+#[test]
+fn test_diff() {
+  use rustix::io::Errno;
+
+  use super::{MockBackend, ProbeBuilder, Step};
+
+  let builder = ProbeBuilder::new().with_v6only(false);
+  let old = builder.with_backend(MockBackend::new()).report();
+  let new = builder
+    .with_backend(MockBackend::new().with_v6only_error(true, Step::Bind, Errno::ADDRNOTAVAIL))
+    .report();
+  let diff = diff(&old, &new);
+  assert_eq!(diff.lost(), Capabilities::IPV6 | Capabilities::UDP_IPV6);
+  assert!(diff.gained().is_empty());
+
+  let changes: Vec<_> = diff.changes().collect();
+  assert_eq!(changes.len(), 2);
+  assert_eq!(changes[0].capability(), Capabilities::IPV6);
+  assert!(!changes[0].available());
+  let reason = changes[0].reason().unwrap();
+  assert_eq!(reason.errno(), Errno::ADDRNOTAVAIL);
+  assert_eq!(
+    changes[0].to_string(),
+    format!("IPv6: available -> unavailable: {reason}")
+  );
+
+  let back = super::diff(&new, &old);
+  assert_eq!(back.gained(), diff.lost());
+  assert_eq!(
+    back.to_string(),
+    "IPv6: unavailable -> available\nUDP IPv6: unavailable -> available"
+  );
+  assert_eq!(
+    ProbeDiff::between(old.probe(), old.probe()).to_string(),
+    "no changes"
+  );
+}
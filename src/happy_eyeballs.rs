@@ -0,0 +1,386 @@
+//! Connection establishment as specified by Happy Eyeballs version 2,
+//! [RFC 8305](https://www.rfc-editor.org/rfc/rfc8305), which skips the
+//! address families [`probe`] reports as unsupported.
+
+use core::time::Duration;
+use std::{
+  io,
+  net::{SocketAddr, TcpStream},
+  time::Instant,
+};
+
+use rustix::{
+  event::{poll, PollFd, PollFlags, Timespec},
+  fd::OwnedFd,
+  io::{ioctl_fionbio, Errno},
+  net::{
+    connect as connect_socket,
+    sockopt::{set_ipv6_v6only, socket_error},
+    AddressFamily, SocketType,
+  },
+};
+
+use super::{
+  address_selection::is_supported, connectivity::is_ipv4_mapped, listener::new_socket, probe, Probe,
+};
+
+const ATTEMPT_DELAY: Duration = Duration::from_millis(250);
+
+/// Configures [`connect`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Config {
+  attempt_delay: Duration,
+  first_address_family_count: usize,
+  timeout: Option<Duration>,
+}
+
+impl Default for Config {
+  #[inline]
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl Config {
+  /// Creates a configuration with the defaults recommended by RFC 8305,
+  /// and no overall timeout.
+  #[inline]
+  pub const fn new() -> Self {
+    Self {
+      attempt_delay: ATTEMPT_DELAY,
+      first_address_family_count: 1,
+      timeout: None,
+    }
+  }
+
+  /// Returns the delay before starting the next attempt while the previous
+  /// ones are still pending, defaults to 250ms.
+  #[inline]
+  pub const fn attempt_delay(&self) -> Duration {
+    self.attempt_delay
+  }
+
+  /// Sets the delay before starting the next attempt while the previous
+  /// ones are still pending.
+  ///
+  /// RFC 8305 recommends a delay between 100ms and 2s.
+  #[inline]
+  pub const fn with_attempt_delay(mut self, delay: Duration) -> Self {
+    self.attempt_delay = delay;
+    self
+  }
+
+  /// Returns the number of addresses of the first family attempted before
+  /// the other family, defaults to `1`.
+  #[inline]
+  pub const fn first_address_family_count(&self) -> usize {
+    self.first_address_family_count
+  }
+
+  /// Sets the number of addresses of the first family attempted before
+  /// the other family. `0` is treated as `1`.
+  #[inline]
+  pub const fn with_first_address_family_count(mut self, count: usize) -> Self {
+    self.first_address_family_count = count;
+    self
+  }
+
+  /// Returns the overall timeout, if any.
+  #[inline]
+  pub const fn timeout(&self) -> Option<Duration> {
+    self.timeout
+  }
+
+  /// Sets the overall timeout, after which all pending attempts are
+  /// abandoned. Without one, each attempt is bounded by the system only.
+  #[inline]
+  pub const fn with_timeout(mut self, timeout: Option<Duration>) -> Self {
+    self.timeout = timeout;
+    self
+  }
+}
+
+/// Connects to the first of `addrs` that accepts the connection.
+///
+/// The family of the first address is preferred, and the addresses are
+/// interleaved by family. Attempts are started one after another, each
+/// after [`Config::attempt_delay`] or as soon as the previous one failed,
+/// and run concurrently. The first established connection wins and all
+/// other pending attempts are closed.
+///
+/// Addresses of a family which is not supported according to [`probe`]
+/// are skipped. `addrs` is otherwise used in the given order, see
+/// [`address_selection`](crate::address_selection) to sort it first.
+///
+/// Returns the error of the last failed attempt if none succeeded.
+pub fn connect(
+  addrs: impl IntoIterator<Item = SocketAddr>,
+  config: &Config,
+) -> io::Result<TcpStream> {
+  connect_with(addrs, config, probe())
+}
+
+fn connect_with(
+  addrs: impl IntoIterator<Item = SocketAddr>,
+  config: &Config,
+  probe: Probe,
+) -> io::Result<TcpStream> {
+  let mut addrs = addrs.into_iter().peekable();
+  if addrs.peek().is_none() {
+    return Err(io::Error::new(
+      io::ErrorKind::InvalidInput,
+      "no addresses to connect to",
+    ));
+  }
+
+  let addrs: Vec<_> = addrs
+    .filter(|addr| is_supported(probe, addr.ip()))
+    .collect();
+  if addrs.is_empty() {
+    return Err(io::Error::new(
+      io::ErrorKind::Unsupported,
+      "no address of a supported family",
+    ));
+  }
+
+  let deadline = config.timeout.map(|timeout| Instant::now() + timeout);
+  let mut addrs = interleave(addrs, config.first_address_family_count).into_iter();
+  let mut pending: Vec<OwnedFd> = Vec::new();
+  let mut next_attempt = Instant::now();
+  let mut last_err = None;
+
+  loop {
+    if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+      return Err(io::ErrorKind::TimedOut.into());
+    }
+
+    if pending.is_empty() || Instant::now() >= next_attempt {
+      match addrs.next() {
+        Some(addr) => {
+          match start(addr) {
+            Ok(sock) => pending.push(sock),
+            // Move on to the next address immediately.
+            Err(e) => {
+              last_err = Some(e);
+              continue;
+            }
+          }
+          next_attempt = Instant::now() + config.attempt_delay;
+        }
+        None if pending.is_empty() => {
+          return Err(last_err.unwrap_or_else(|| io::ErrorKind::NotConnected.into()));
+        }
+        None => {}
+      }
+    }
+
+    // Wait for an attempt to complete, or for the next one to be due.
+    let wake = match (deadline, addrs.as_slice().is_empty()) {
+      (Some(deadline), false) => Some(deadline.min(next_attempt)),
+      (None, false) => Some(next_attempt),
+      (deadline, true) => deadline,
+    };
+    let timeout = wake
+      .map(|wake| Timespec::try_from(wake.saturating_duration_since(Instant::now())))
+      .transpose()
+      .map_err(|_| io::Error::from(io::ErrorKind::InvalidInput))?;
+    let mut fds: Vec<_> = pending
+      .iter()
+      .map(|sock| PollFd::new(sock, PollFlags::OUT))
+      .collect();
+    match poll(&mut fds, timeout.as_ref()) {
+      Ok(_) => {}
+      Err(Errno::INTR) => continue,
+      Err(e) => return Err(e.into()),
+    }
+
+    let ready: Vec<_> = fds
+      .iter()
+      .enumerate()
+      .filter(|(_, fd)| !fd.revents().is_empty())
+      .map(|(i, _)| i)
+      .collect();
+    drop(fds);
+
+    for &i in &ready {
+      match socket_error(&pending[i]).and_then(|res| res) {
+        Ok(()) => return finish(pending.swap_remove(i)),
+        Err(e) => last_err = Some(e.into()),
+      }
+    }
+
+    if !ready.is_empty() {
+      for &i in ready.iter().rev() {
+        pending.remove(i);
+      }
+      // A failed attempt starts the next one immediately.
+      next_attempt = Instant::now();
+    }
+  }
+}
+
+/// Starts a nonblocking connection attempt to `addr`.
+fn start(addr: SocketAddr) -> io::Result<OwnedFd> {
+  let family = match addr {
+    SocketAddr::V4(_) => AddressFamily::INET,
+    SocketAddr::V6(_) => AddressFamily::INET6,
+  };
+  let sock = new_socket(family, SocketType::STREAM)?;
+
+  if is_ipv4_mapped(addr) {
+    set_ipv6_v6only(&sock, false)?;
+  }
+
+  ioctl_fionbio(&sock, true)?;
+  match connect_socket(&sock, &addr) {
+    Ok(()) | Err(Errno::INPROGRESS | Errno::WOULDBLOCK) => Ok(sock),
+    Err(e) => Err(e.into()),
+  }
+}
+
+/// Turns the established connection back into a blocking stream.
+fn finish(sock: OwnedFd) -> io::Result<TcpStream> {
+  ioctl_fionbio(&sock, false)?;
+  Ok(TcpStream::from(sock))
+}
+
+/// Orders `addrs` so that `first_family_count` addresses of the family of
+/// the first address come first, then alternates between the families.
+fn interleave(addrs: Vec<SocketAddr>, first_family_count: usize) -> Vec<SocketAddr> {
+  let Some(first) = addrs.first() else {
+    return addrs;
+  };
+
+  let (len, first_ipv6) = (addrs.len(), first.is_ipv6());
+  let (preferred, other): (Vec<_>, Vec<_>) = addrs
+    .into_iter()
+    .partition(|addr| addr.is_ipv6() == first_ipv6);
+  let (mut preferred, mut other) = (preferred.into_iter(), other.into_iter());
+
+  let mut out = Vec::with_capacity(len);
+  out.extend(
+    preferred
+      .by_ref()
+      .take(first_family_count.saturating_sub(1)),
+  );
+  loop {
+    match (preferred.next(), other.next()) {
+      (None, None) => return out,
+      (a, b) => out.extend(a.into_iter().chain(b)),
+    }
+  }
+}
+
+#[test]
+fn test_interleave() {
+  let v4 = |n| SocketAddr::new(std::net::Ipv4Addr::new(10, 0, 0, n).into(), 0);
+  let v6 = |n| {
+    SocketAddr::new(
+      std::net::Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, n).into(),
+      0,
+    )
+  };
+
+  let addrs = vec![v6(1), v6(2), v6(3), v4(1), v4(2)];
+  assert_eq!(
+    interleave(addrs.clone(), 1),
+    [v6(1), v4(1), v6(2), v4(2), v6(3)]
+  );
+  assert_eq!(
+    interleave(addrs.clone(), 2),
+    [v6(1), v6(2), v4(1), v6(3), v4(2)]
+  );
+  assert_eq!(interleave(addrs, 0)[..2], [v6(1), v4(1)]);
+  assert_eq!(interleave(vec![v4(1), v6(1)], 1), [v4(1), v6(1)]);
+}
+
+#[test]
+fn test_connect() {
+  use std::net::TcpListener;
+
+  use crate::Capabilities;
+
+  let ipv4_only = Probe::from_capabilities(Capabilities::IPV4);
+  let ipv6_only = Probe::from_capabilities(Capabilities::IPV6);
+  let dual = Probe::from_capabilities(Capabilities::all());
+
+  let config = Config::new().with_attempt_delay(Duration::from_secs(10));
+  let ipv4 = TcpListener::bind("127.0.0.1:0").unwrap();
+  let ipv4_addr = ipv4.local_addr().unwrap();
+  let closed = TcpListener::bind("127.0.0.1:0")
+    .unwrap()
+    .local_addr()
+    .unwrap();
+
+  let stream = connect_with([ipv4_addr], &config, ipv4_only).unwrap();
+  assert_eq!(stream.peer_addr().unwrap(), ipv4_addr);
+
+  // A refused attempt moves on to the next address without waiting.
+  let start = Instant::now();
+  let stream = connect_with([closed, ipv4_addr], &config, ipv4_only).unwrap();
+  assert_eq!(stream.peer_addr().unwrap(), ipv4_addr);
+  assert!(start.elapsed() < config.attempt_delay());
+
+  assert_eq!(
+    connect_with([], &config, dual).unwrap_err().kind(),
+    io::ErrorKind::InvalidInput
+  );
+  assert_eq!(
+    connect_with([ipv4_addr], &config, ipv6_only)
+      .unwrap_err()
+      .kind(),
+    io::ErrorKind::Unsupported
+  );
+
+  if probe().ipv6() {
+    let ipv6 = TcpListener::bind("[::1]:0").unwrap();
+    let ipv6_addr = ipv6.local_addr().unwrap();
+
+    let stream = connect_with([ipv6_addr, ipv4_addr], &config, dual).unwrap();
+    assert_eq!(stream.peer_addr().unwrap(), ipv6_addr);
+
+    // IPv6 is skipped if it is reported as unsupported.
+    let stream = connect_with([ipv6_addr, ipv4_addr], &config, ipv4_only).unwrap();
+    assert_eq!(stream.peer_addr().unwrap(), ipv4_addr);
+  }
+}
+
+// Linux drops SYNs to a listener whose accept queue is full, which stalls
+// the connection attempts to it.
+#[cfg(target_os = "linux")]
+#[test]
+fn test_connect_stalled() {
+  use rustix::net::{bind, getsockname, listen};
+
+  use crate::Capabilities;
+
+  let ipv4_only = Probe::from_capabilities(Capabilities::IPV4);
+
+  let stalled = new_socket(AddressFamily::INET, SocketType::STREAM).unwrap();
+  bind(
+    &stalled,
+    &SocketAddr::from((std::net::Ipv4Addr::LOCALHOST, 0)),
+  )
+  .unwrap();
+  listen(&stalled, 0).unwrap();
+  let stalled_addr = SocketAddr::try_from(getsockname(&stalled).unwrap()).unwrap();
+  let _queued = TcpStream::connect(stalled_addr).unwrap();
+
+  let ipv4 = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+  let ipv4_addr = ipv4.local_addr().unwrap();
+
+  let delay = Duration::from_millis(50);
+  let config = Config::new().with_attempt_delay(delay);
+  let start = Instant::now();
+  let stream = connect_with([stalled_addr, ipv4_addr], &config, ipv4_only).unwrap();
+  assert_eq!(stream.peer_addr().unwrap(), ipv4_addr);
+  assert!(start.elapsed() >= delay);
+
+  let config = config.with_timeout(Some(Duration::from_millis(100)));
+  assert_eq!(
+    connect_with([stalled_addr], &config, ipv4_only)
+      .unwrap_err()
+      .kind(),
+    io::ErrorKind::TimedOut
+  );
+}
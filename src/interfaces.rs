@@ -0,0 +1,326 @@
+use std::{io, net::IpAddr};
+
+use super::address_selection::{Scope, SourceCandidate};
+
+const IFF_UP: u32 = 0x1;
+const IFF_LOOPBACK: u32 = 0x8;
+const IFF_RUNNING: u32 = 0x40;
+const IFF_MULTICAST: u32 = 0x1000;
+
+const IFA_F_TEMPORARY: u32 = 0x1;
+const IFA_F_DEPRECATED: u32 = 0x20;
+const IFA_F_TENTATIVE: u32 = 0x40;
+
+/// A network interface of the host and its addresses.
+///
+/// See [`interfaces`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Interface {
+  name: String,
+  index: u32,
+  flags: u32,
+  addrs: Vec<InterfaceAddr>,
+}
+
+impl Interface {
+  /// Returns the name of the interface, e.g. `lo` or `eth0`.
+  #[inline]
+  pub fn name(&self) -> &str {
+    &self.name
+  }
+
+  /// Returns the index of the interface, which is also the scope id of its
+  /// link-local IPv6 addresses.
+  #[inline]
+  pub const fn index(&self) -> u32 {
+    self.index
+  }
+
+  /// Returns `true` if the interface is administratively up.
+  #[inline]
+  pub const fn is_up(&self) -> bool {
+    self.flags & IFF_UP != 0
+  }
+
+  /// Returns `true` if the interface is a loopback interface.
+  #[inline]
+  pub const fn is_loopback(&self) -> bool {
+    self.flags & IFF_LOOPBACK != 0
+  }
+
+  /// Returns `true` if the interface supports multicast.
+  #[inline]
+  pub const fn is_multicast(&self) -> bool {
+    self.flags & IFF_MULTICAST != 0
+  }
+
+  /// Returns `true` if the interface is operational, i.e. the link is up.
+  #[inline]
+  pub const fn is_running(&self) -> bool {
+    self.flags & IFF_RUNNING != 0
+  }
+
+  /// Returns the addresses assigned to the interface.
+  #[inline]
+  pub fn addrs(&self) -> &[InterfaceAddr] {
+    &self.addrs
+  }
+}
+
+/// An address assigned to an [`Interface`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct InterfaceAddr {
+  addr: IpAddr,
+  prefix_len: u8,
+  flags: u32,
+}
+
+impl InterfaceAddr {
+  /// Returns the address.
+  #[inline]
+  pub const fn addr(&self) -> IpAddr {
+    self.addr
+  }
+
+  /// Returns the length of the prefix of the subnet of the address.
+  #[inline]
+  pub const fn prefix_len(&self) -> u8 {
+    self.prefix_len
+  }
+
+  /// Returns the scope of the address.
+  #[inline]
+  pub fn scope(&self) -> Scope {
+    Scope::of(self.addr)
+  }
+
+  /// Returns `true` if the address is a temporary IPv6 privacy address.
+  #[inline]
+  pub const fn is_temporary(&self) -> bool {
+    self.flags & IFA_F_TEMPORARY != 0
+  }
+
+  /// Returns `true` if the preferred lifetime of the address has expired.
+  #[inline]
+  pub const fn is_deprecated(&self) -> bool {
+    self.flags & IFA_F_DEPRECATED != 0
+  }
+
+  /// Returns `true` if duplicate address detection has not completed yet,
+  /// in which case the address cannot be used.
+  #[inline]
+  pub const fn is_tentative(&self) -> bool {
+    self.flags & IFA_F_TENTATIVE != 0
+  }
+}
+
+impl From<InterfaceAddr> for SourceCandidate {
+  #[inline]
+  fn from(addr: InterfaceAddr) -> Self {
+    SourceCandidate::new(addr.addr)
+      .with_deprecated(addr.is_deprecated())
+      .with_temporary(addr.is_temporary())
+  }
+}
+
+/// Returns the network interfaces of the host and their addresses, ordered
+/// by index.
+///
+/// The interfaces are gathered from rtnetlink on Linux, other platforms
+/// fail with [`io::ErrorKind::Unsupported`].
+pub fn interfaces() -> io::Result<Vec<Interface>> {
+  imp::interfaces()
+}
+
+/// Returns `false` if the loopback interface is known to be down, e.g. in a
+/// fresh network namespace, where addresses can be bound but connections
+/// over loopback fail.
+#[cfg(test)]
+pub(crate) fn loopback_up() -> bool {
+  interfaces().map_or(true, |ifaces| {
+    ifaces
+      .iter()
+      .any(|iface| iface.is_loopback() && iface.is_up())
+  })
+}
+
+/// Returns the network interfaces without their addresses, ordered by index.
+#[cfg(target_os = "linux")]
+#[inline]
+pub(crate) fn links() -> io::Result<Vec<Interface>> {
+  imp::links()
+}
+
+#[cfg(target_os = "linux")]
+mod imp {
+  use std::net::{Ipv4Addr, Ipv6Addr};
+
+  use super::*;
+  use crate::netlink::{self, Attributes};
+
+  const AF_INET: u8 = 2;
+  const AF_INET6: u8 = 10;
+
+  /// `sizeof(struct ifinfomsg)`
+  const IFINFOMSG_LEN: usize = 16;
+  /// `sizeof(struct ifaddrmsg)`
+  const IFADDRMSG_LEN: usize = 8;
+
+  const IFLA_IFNAME: u16 = 3;
+
+  const IFA_ADDRESS: u16 = 1;
+  const IFA_LOCAL: u16 = 2;
+  const IFA_FLAGS: u16 = 8;
+
+  pub(super) fn interfaces() -> io::Result<Vec<Interface>> {
+    let mut ifaces = links()?;
+    netlink::dump(netlink::RTM_GETADDR, &[0; IFADDRMSG_LEN], |hdr, payload| {
+      if hdr.ty != netlink::RTM_NEWADDR {
+        return;
+      }
+
+      if let Some((index, addr)) = parse_addr(payload) {
+        if let Ok(i) = ifaces.binary_search_by_key(&index, |iface| iface.index) {
+          ifaces[i].addrs.push(addr);
+        }
+      }
+    })?;
+
+    Ok(ifaces)
+  }
+
+  pub(super) fn links() -> io::Result<Vec<Interface>> {
+    let mut ifaces = Vec::new();
+    netlink::dump(netlink::RTM_GETLINK, &[0; IFINFOMSG_LEN], |hdr, payload| {
+      if hdr.ty == netlink::RTM_NEWLINK {
+        ifaces.extend(parse_link(payload));
+      }
+    })?;
+    ifaces.sort_by_key(|iface: &Interface| iface.index);
+    Ok(ifaces)
+  }
+
+  /// Parses the payload of a `RTM_NEWLINK` message.
+  pub(super) fn parse_link(payload: &[u8]) -> Option<Interface> {
+    let index = u32::from_ne_bytes(payload.get(4..8)?.try_into().unwrap());
+    let flags = u32::from_ne_bytes(payload.get(8..12)?.try_into().unwrap());
+    let name = Attributes::new(payload.get(IFINFOMSG_LEN..)?)
+      .find(|(ty, _)| *ty == IFLA_IFNAME)
+      .map(|(_, name)| {
+        let name = name.split(|b| *b == 0).next().unwrap_or_default();
+        String::from_utf8_lossy(name).into_owned()
+      })?;
+
+    Some(Interface {
+      name,
+      index,
+      flags,
+      addrs: Vec::new(),
+    })
+  }
+
+  /// Parses the payload of a `RTM_NEWADDR` message, returns the index of
+  /// the interface and the address.
+  pub(super) fn parse_addr(payload: &[u8]) -> Option<(u32, InterfaceAddr)> {
+    let (family, prefix_len) = (*payload.first()?, *payload.get(1)?);
+    let mut flags = u32::from(*payload.get(2)?);
+    let index = u32::from_ne_bytes(payload.get(4..8)?.try_into().unwrap());
+
+    let (mut address, mut local) = (None, None);
+    for (ty, data) in Attributes::new(payload.get(IFADDRMSG_LEN..)?) {
+      let addr = match (family, data.len()) {
+        (AF_INET, 4) => Some(IpAddr::V4(Ipv4Addr::from(
+          <[u8; 4]>::try_from(data).unwrap(),
+        ))),
+        (AF_INET6, 16) => Some(IpAddr::V6(Ipv6Addr::from(
+          <[u8; 16]>::try_from(data).unwrap(),
+        ))),
+        _ => None,
+      };
+
+      match ty {
+        IFA_ADDRESS => address = addr,
+        IFA_LOCAL => local = addr,
+        // Supersedes the 8 bit flags of the header.
+        IFA_FLAGS if data.len() == 4 => flags = u32::from_ne_bytes(data.try_into().unwrap()),
+        _ => {}
+      }
+    }
+
+    // `IFA_ADDRESS` is the peer of point-to-point links, if `IFA_LOCAL` is
+    // present.
+    let addr = local.or(address)?;
+    Some((
+      index,
+      InterfaceAddr {
+        addr,
+        prefix_len,
+        flags,
+      },
+    ))
+  }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod imp {
+  use super::*;
+
+  pub(super) fn interfaces() -> io::Result<Vec<Interface>> {
+    Err(io::Error::new(
+      io::ErrorKind::Unsupported,
+      "interface enumeration is only supported on Linux",
+    ))
+  }
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn test_parse() {
+  use crate::netlink::attribute;
+
+  let mut link = vec![0; 16];
+  link[4..8].copy_from_slice(&1u32.to_ne_bytes());
+  link[8..12].copy_from_slice(&(IFF_UP | IFF_LOOPBACK | IFF_RUNNING).to_ne_bytes());
+  link.extend(attribute(3, b"lo\0"));
+  let iface = imp::parse_link(&link).unwrap();
+  assert_eq!((iface.name(), iface.index()), ("lo", 1));
+  assert!(iface.is_up() && iface.is_loopback() && iface.is_running());
+  assert!(!iface.is_multicast());
+
+  let mut addr = vec![10, 64, 0, 0];
+  addr.extend(2u32.to_ne_bytes());
+  addr.extend(attribute(
+    1,
+    &"fe80::1".parse::<std::net::Ipv6Addr>().unwrap().octets(),
+  ));
+  addr.extend(attribute(
+    8,
+    &(IFA_F_TEMPORARY | IFA_F_DEPRECATED).to_ne_bytes(),
+  ));
+  let (index, addr) = imp::parse_addr(&addr).unwrap();
+  assert_eq!(index, 2);
+  assert_eq!(addr.addr(), "fe80::1".parse::<IpAddr>().unwrap());
+  assert_eq!((addr.prefix_len(), addr.scope()), (64, Scope::LinkLocal));
+  assert!(addr.is_temporary() && addr.is_deprecated() && !addr.is_tentative());
+
+  // `IFA_LOCAL` wins over the peer address of point-to-point links.
+  let mut addr = vec![2, 32, 0, 0];
+  addr.extend(3u32.to_ne_bytes());
+  addr.extend(attribute(1, &[10, 0, 0, 2]));
+  addr.extend(attribute(2, &[10, 0, 0, 1]));
+  assert_eq!(
+    imp::parse_addr(&addr).unwrap().1.addr(),
+    IpAddr::from([10, 0, 0, 1])
+  );
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn test_interfaces() {
+  if let Some(ifaces) = crate::netlink::allowed(interfaces()) {
+    assert!(ifaces.windows(2).all(|w| w[0].index() < w[1].index()));
+    assert!(ifaces.iter().all(|iface| !iface.name().is_empty()));
+  }
+}
@@ -4,55 +4,170 @@
 #![cfg_attr(docsrs, allow(unused_attributes))]
 #![deny(missing_docs)]
 
-use std::sync::OnceLock;
+use core::time::Duration;
+use std::{
+  net::SocketAddr,
+  sync::{PoisonError, RwLock},
+};
 
-use rustix::net::{bind, ipproto, socket, sockopt::set_ipv6_v6only, AddressFamily, SocketType};
+pub use addr::{
+  loopback_addr, loopback_socket_addr, unspecified_addr, unspecified_socket_addr, AddrPolicy,
+};
+pub use backend::{MockBackend, MockSocket, RustixBackend, SocketBackend};
+pub use builder::ProbeBuilder;
+pub use capabilities::Capabilities;
+pub use connectivity::ConnectivityReport;
+pub use diff::{diff, CapabilityChange, ProbeDiff};
+pub use interfaces::{interfaces, Interface, InterfaceAddr};
+pub use listener::{
+  bind_any, bind_any_udp, BindStrategy, DualStack, DualStackListener, DualStackUdpSocket,
+};
+#[cfg(feature = "tokio")]
+#[cfg_attr(docsrs, doc(cfg(feature = "tokio")))]
+pub use listener::{bind_any_async, bind_any_udp_async};
+pub use overrides::{
+  clear_override, overrides, scoped_override, set_override, OverrideGuard, Overrides,
+};
+pub use reachability::Reachability;
+pub use report::{ProbeError, ProbeReport, Reason, Step, TransportReport};
+pub use routes::{default_route_v4, default_route_v6, Route};
+pub use v6only::V6OnlyReport;
 
-static INIT: OnceLock<Probe> = OnceLock::new();
+#[cfg(feature = "serde")]
+#[cfg_attr(docsrs, doc(cfg(feature = "serde")))]
+pub use snapshot::Snapshot;
 
-const V6_PROBES: [(bool, bool); 2] = [
-  (true, true),   // IPv6
-  (false, false), // IPv4-mapped
-];
+#[cfg(target_os = "linux")]
+#[cfg_attr(docsrs, doc(cfg(target_os = "linux")))]
+pub use sandbox::{sandbox, FamilyReport, FamilySupport, SandboxReport};
+#[cfg(target_os = "linux")]
+#[cfg_attr(docsrs, doc(cfg(target_os = "linux")))]
+pub use sysctl::Ipv6Sysctls;
+#[cfg(target_os = "linux")]
+#[cfg_attr(docsrs, doc(cfg(target_os = "linux")))]
+pub use watch::{watch, ProbeChange, Watcher};
+
+#[cfg(all(target_os = "linux", feature = "tokio"))]
+#[cfg_attr(docsrs, doc(cfg(all(target_os = "linux", feature = "tokio"))))]
+pub use watch::AsyncWatcher;
+
+pub mod address_selection;
+pub mod happy_eyeballs;
+
+mod addr;
+mod backend;
+mod builder;
+mod capabilities;
+mod connectivity;
+mod diff;
+mod interfaces;
+mod listener;
+mod overrides;
+mod reachability;
+mod report;
+mod routes;
+mod v6only;
+
+#[cfg(feature = "serde")]
+mod snapshot;
+
+#[cfg(target_os = "linux")]
+mod netlink;
+#[cfg(target_os = "linux")]
+mod sandbox;
+#[cfg(target_os = "linux")]
+mod sysctl;
+#[cfg(target_os = "linux")]
+mod watch;
+
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(1);
+
+static STATE: RwLock<Option<Probe>> = RwLock::new(None);
 
 /// Returns `true` if the system supports IPv4 communication.
 pub fn ipv4() -> bool {
-  probe().ipv4
+  probe().ipv4()
 }
 
 /// Returns `true` if the system supports IPv6 communication.
 pub fn ipv6() -> bool {
-  probe().ipv6
+  probe().ipv6()
 }
 
 /// Returns `true` if the system understands
 /// IPv4-mapped IPv6.
 pub fn ipv4_mapped_ipv6() -> bool {
-  probe().ipv4_mapped_ipv6
+  probe().ipv4_mapped_ipv6()
 }
 
 /// Represents the IP stack communication capabilities of the system.
+///
+/// The top level accessors, e.g. [`Probe::ipv4`], report the capabilities of
+/// TCP stream sockets, use [`Probe::udp`] for UDP datagram sockets.
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Probe {
+  tcp: TransportProbe,
+  udp: TransportProbe,
+}
+
+impl Probe {
+  /// Returns `true` if the system supports IPv4 communication.
+  #[inline]
+  pub const fn ipv4(&self) -> bool {
+    self.tcp.ipv4
+  }
+
+  /// Returns `true` if the system supports IPv6 communication.
+  #[inline]
+  pub const fn ipv6(&self) -> bool {
+    self.tcp.ipv6
+  }
+
+  /// Returns `true` if the system understands
+  /// IPv4-mapped IPv6.
+  #[inline]
+  pub const fn ipv4_mapped_ipv6(&self) -> bool {
+    self.tcp.ipv4_mapped_ipv6
+  }
+
+  /// Returns the capabilities of TCP stream sockets.
+  #[inline]
+  pub const fn tcp(&self) -> TransportProbe {
+    self.tcp
+  }
+
+  /// Returns the capabilities of UDP datagram sockets.
+  #[inline]
+  pub const fn udp(&self) -> TransportProbe {
+    self.udp
+  }
+}
+
+/// Represents the IP stack communication capabilities of a single
+/// transport protocol.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TransportProbe {
   ipv4: bool,
   ipv6: bool,
   ipv4_mapped_ipv6: bool,
 }
 
-impl Probe {
-  /// Returns `true` if the system supports IPv4 communication.
+impl TransportProbe {
+  /// Returns `true` if the transport supports IPv4 communication.
   #[inline]
   pub const fn ipv4(&self) -> bool {
     self.ipv4
   }
 
-  /// Returns `true` if the system supports IPv6 communication.
+  /// Returns `true` if the transport supports IPv6 communication.
   #[inline]
   pub const fn ipv6(&self) -> bool {
     self.ipv6
   }
 
-  /// Returns `true` if the system understands
+  /// Returns `true` if the transport understands
   /// IPv4-mapped IPv6.
   #[inline]
   pub const fn ipv4_mapped_ipv6(&self) -> bool {
@@ -70,69 +185,157 @@ impl Probe {
 /// the IPv6 interface. That simplifies our code and is most
 /// general. Unfortunately, we need to run on kernels built without
 /// IPv6 support too. So probe the kernel to figure it out.
+///
+/// The result of the first call is cached, use [`refresh`] to replace it
+/// or [`probe_fresh`] to bypass it.
+///
+/// The overrides of [`set_override`] and the `IPROBE_IPV4`, `IPROBE_IPV6`
+/// and `IPROBE_IPV4_MAPPED` environment variables take precedence over the
+/// probed capabilities, see [`Overrides`].
 pub fn probe() -> Probe {
-  *INIT.get_or_init(probe_in)
+  let overrides = overrides();
+  if let Some(probe) = *STATE.read().unwrap_or_else(PoisonError::into_inner) {
+    return overrides.apply(probe);
+  }
+
+  let mut state = STATE.write().unwrap_or_else(PoisonError::into_inner);
+  overrides.apply(*state.get_or_insert_with(|| summary().probe()))
+}
+
+/// The asynchronous version of [`probe`], which does not block the tokio
+/// runtime.
+///
+/// Returns the cached result if there is one, otherwise the checks run on
+/// the blocking thread pool and their result is cached. Must be called
+/// within a tokio runtime.
+#[cfg(feature = "tokio")]
+#[cfg_attr(docsrs, doc(cfg(feature = "tokio")))]
+pub async fn probe_async() -> Probe {
+  let overrides = overrides();
+  if let Some(probe) = *STATE.read().unwrap_or_else(PoisonError::into_inner) {
+    return overrides.apply(probe);
+  }
+
+  let probe = summary().report_async().await.probe();
+  // A concurrent call may have finished first.
+  overrides.apply(
+    *STATE
+      .write()
+      .unwrap_or_else(PoisonError::into_inner)
+      .get_or_insert(probe),
+  )
 }
 
-fn probe_in() -> Probe {
-  use std::net::{Ipv6Addr, SocketAddrV6};
+/// Probes the system like [`probe`], but bypasses the cached result.
+///
+/// The cached result is left untouched, use [`refresh`] to update it.
+/// The overrides of [`set_override`] are not applied, like in the reports.
+pub fn probe_fresh() -> Probe {
+  summary().probe()
+}
+
+/// Probes the system again and replaces the cached result, so that
+/// subsequent calls to [`probe`], [`ipv4`], [`ipv6`] and [`ipv4_mapped_ipv6`]
+/// observe the latest capabilities.
+///
+/// Returns the new result, with the overrides applied like [`probe`].
+pub fn refresh() -> Probe {
+  let probe = summary().probe();
+  *STATE.write().unwrap_or_else(PoisonError::into_inner) = Some(probe);
+  overrides().apply(probe)
+}
 
-  let mut caps = Probe {
-    ipv4: false,
-    ipv6: false,
-    ipv4_mapped_ipv6: false,
-  };
+/// Probes IPv4, IPv6 and IPv4-mapped IPv6 communication capabilities
+/// like [`probe`], but reports which step of each check failed and why.
+///
+/// The result is not cached, every call probes the system again.
+/// Use [`ProbeBuilder`] to configure which checks are run.
+pub fn try_probe() -> ProbeReport {
+  ProbeBuilder::new().report()
+}
+
+/// The checks of [`probe`] and its variants, which only need the
+/// capabilities.
+const fn summary() -> ProbeBuilder {
+  ProbeBuilder::new().with_v6only(false).with_sysctls(false)
+}
+
+/// Probes the system like [`try_probe`], and additionally checks end-to-end
+/// connectivity over loopback for IPv4, IPv6 and IPv4-mapped IPv6, as well
+/// as whether a dual-stack listener works, see [`dual_stack_listener_works`].
+///
+/// A successful bind does not prove that a connection works, e.g. firewall
+/// rules may drop loopback traffic or the loopback interface may be down.
+/// For each family, a TCP listener is created, connected to, and a byte is
+/// exchanged over the connection. Each check gives up after `timeout`.
+///
+/// The results are available through [`ProbeReport::connectivity`] and
+/// [`ProbeReport::dual_stack_listener`], the bind level results are
+/// reported as usual.
+pub fn try_probe_deep(timeout: Duration) -> ProbeReport {
+  ProbeBuilder::new()
+    .with_deep(true)
+    .with_dual_stack_listener(true)
+    .with_timeout(timeout)
+    .report()
+}
+
+/// The asynchronous version of [`try_probe_deep`], whose connectivity
+/// checks wait for their sockets with tokio, see
+/// [`ProbeBuilder::report_async`].
+#[cfg(feature = "tokio")]
+#[cfg_attr(docsrs, doc(cfg(feature = "tokio")))]
+pub async fn try_probe_deep_async(timeout: Duration) -> ProbeReport {
+  ProbeBuilder::new()
+    .with_deep(true)
+    .with_dual_stack_listener(true)
+    .with_timeout(timeout)
+    .report_async()
+    .await
+}
 
+/// Returns `true` if a single IPv6 wildcard listener accepts IPv4 clients.
+///
+/// Unlike [`ipv4_mapped_ipv6`], which only binds `::ffff:127.0.0.1`, this
+/// binds `[::]:0` with `IPV6_V6ONLY` disabled, connects to it from an IPv4
+/// socket via `127.0.0.1`, and confirms that the listener sees the client as
+/// an IPv4-mapped IPv6 address. The result is not cached.
+pub fn dual_stack_listener_works() -> bool {
+  try_dual_stack_listener(DEFAULT_TIMEOUT).is_ok_and(connectivity::is_ipv4_mapped)
+}
+
+/// Checks whether a single IPv6 wildcard listener accepts IPv4 clients,
+/// giving up after `timeout`, see [`dual_stack_listener_works`].
+///
+/// Returns the address of the IPv4 client as seen by the listener.
+pub fn try_dual_stack_listener(timeout: Duration) -> Result<SocketAddr, ProbeError> {
   #[cfg(windows)]
   let _ = rustix::net::wsa_startup();
 
-  // Check IPv4 support
-  {
-    let ipv4_sock = socket(AddressFamily::INET, SocketType::STREAM, Some(ipproto::TCP));
+  let res = connectivity::dual_stack(&RustixBackend, timeout);
 
-    if ipv4_sock.is_ok() {
-      caps.ipv4 = true;
-    }
-  }
+  #[cfg(windows)]
+  let _ = rustix::net::wsa_cleanup();
 
-  // Probe IPv6 and IPv4-mapped IPv6
-  for (is_ipv6, v6_only) in V6_PROBES {
-    let sock = socket(AddressFamily::INET6, SocketType::STREAM, Some(ipproto::TCP));
-
-    if let Ok(sock) = sock {
-      // Set IPV6_V6ONLY option
-      let _ = set_ipv6_v6only(&sock, v6_only);
-
-      // Create bind address
-      let addr = if is_ipv6 {
-        SocketAddrV6::new(Ipv6Addr::LOCALHOST, 0, 0, 0)
-      } else {
-        SocketAddrV6::new(
-          // ::ffff:127.0.0.1
-          Ipv6Addr::new(0, 0, 0, 0, 0, 0xffff, 0x7f00, 0x01),
-          0,
-          0,
-          0,
-        )
-      };
-
-      // Attempt to bind
-      let bind_result = bind(sock, &addr);
-
-      if bind_result.is_ok() {
-        if is_ipv6 {
-          caps.ipv6 = true;
-        } else {
-          caps.ipv4_mapped_ipv6 = true;
-        }
-      }
-    }
-  }
+  res
+}
+
+/// Checks the `IPV6_V6ONLY` behaviour of new IPv6 sockets.
+///
+/// Unlike the IPv4-mapped IPv6 check, which sets `IPV6_V6ONLY` explicitly,
+/// this reads the default of a fresh socket, which is what libraries that
+/// never set the option get, and reports whether disabling it is rejected.
+/// On Linux, the default is controlled by `net.ipv6.bindv6only`.
+pub fn try_v6only() -> V6OnlyReport {
+  #[cfg(windows)]
+  let _ = rustix::net::wsa_startup();
+
+  let report = v6only::check(&RustixBackend, v6only::bindv6only());
 
   #[cfg(windows)]
   let _ = rustix::net::wsa_cleanup();
 
-  caps
+  report
 }
 
 #[test]
@@ -141,4 +344,33 @@ fn test() {
   println!("IPv4 enabled: {}", caps.ipv4());
   println!("IPv6 enabled: {}", caps.ipv6());
   println!("IPv4-mapped IPv6 enabled: {}", caps.ipv4_mapped_ipv6());
+  println!("UDP IPv4 enabled: {}", caps.udp().ipv4());
+  println!("UDP IPv6 enabled: {}", caps.udp().ipv6());
+  println!(
+    "UDP IPv4-mapped IPv6 enabled: {}",
+    caps.udp().ipv4_mapped_ipv6()
+  );
+}
+
+#[test]
+fn test_try_probe() {
+  let report = try_probe();
+  assert_eq!(report.overrides().apply(report.probe()), probe());
+  assert_eq!(refresh(), probe());
+  println!("IPv4: {:?}", report.ipv4());
+  println!("IPv6: {:?}", report.ipv6());
+  println!("IPv4-mapped IPv6: {:?}", report.ipv4_mapped_ipv6());
+}
+
+#[cfg(all(test, feature = "tokio"))]
+#[tokio::test]
+async fn test_probe_async() {
+  assert_eq!(probe_async().await, probe());
+
+  let report = try_probe_deep_async(Duration::from_secs(1)).await;
+  assert_eq!(report.probe(), probe_fresh());
+  assert_eq!(
+    report.dual_stack_listener_works(),
+    dual_stack_listener_works()
+  );
 }
@@ -0,0 +1,366 @@
+use std::{
+  io,
+  net::{Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV4, SocketAddrV6, TcpListener, UdpSocket},
+};
+
+use rustix::{
+  fd::OwnedFd,
+  net::{bind, getsockname, listen, sockopt, AddressFamily, SocketType},
+};
+
+use super::{probe, TransportProbe};
+
+const BACKLOG: i32 = 128;
+
+/// How many ephemeral ports [`BindStrategy::Separate`] tries before giving
+/// up, the port picked for IPv4 may be taken on IPv6.
+const SEPARATE_ATTEMPTS: usize = 8;
+
+/// The strategy chosen to bind the wildcard addresses of both families.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum BindStrategy {
+  /// A single `[::]` socket with `IPV6_V6ONLY` disabled, which serves both
+  /// IPv4 and IPv6 peers.
+  DualStack,
+  /// Separate `0.0.0.0` and `[::]` sockets, the latter with `IPV6_V6ONLY`
+  /// enabled.
+  Separate,
+  /// A single `0.0.0.0` socket, because IPv6 is not supported.
+  Ipv4Only,
+  /// A single `[::]` socket, because IPv4 is not supported.
+  Ipv6Only,
+}
+
+impl BindStrategy {
+  /// Chooses the best strategy for the given capabilities, returns `None`
+  /// if neither IPv4 nor IPv6 is supported.
+  pub const fn choose(caps: TransportProbe) -> Option<Self> {
+    match (caps.ipv4(), caps.ipv6(), caps.ipv4_mapped_ipv6()) {
+      (_, true, true) => Some(Self::DualStack),
+      (true, true, false) => Some(Self::Separate),
+      (true, false, _) => Some(Self::Ipv4Only),
+      (false, true, false) => Some(Self::Ipv6Only),
+      (false, false, _) => None,
+    }
+  }
+}
+
+/// One or two sockets bound to the wildcard addresses, see [`bind_any`].
+#[derive(Debug)]
+pub struct DualStack<S> {
+  primary: S,
+  secondary: Option<S>,
+  strategy: BindStrategy,
+}
+
+/// TCP listeners bound to the wildcard addresses, see [`bind_any`].
+pub type DualStackListener = DualStack<TcpListener>;
+
+/// UDP sockets bound to the wildcard addresses, see [`bind_any_udp`].
+pub type DualStackUdpSocket = DualStack<UdpSocket>;
+
+impl<S> DualStack<S> {
+  /// Returns the strategy used to bind the sockets.
+  #[inline]
+  pub const fn strategy(&self) -> BindStrategy {
+    self.strategy
+  }
+
+  /// Returns the first socket, which is the IPv4 one for
+  /// [`BindStrategy::Separate`].
+  #[inline]
+  pub const fn primary(&self) -> &S {
+    &self.primary
+  }
+
+  /// Returns the IPv6 socket for [`BindStrategy::Separate`].
+  #[inline]
+  pub const fn secondary(&self) -> Option<&S> {
+    self.secondary.as_ref()
+  }
+
+  /// Returns an iterator over the bound sockets.
+  #[inline]
+  pub fn iter(&self) -> impl Iterator<Item = &S> {
+    core::iter::once(&self.primary).chain(self.secondary.as_ref())
+  }
+
+  /// Consumes the `DualStack`, returning the primary and the secondary socket.
+  #[inline]
+  pub fn into_parts(self) -> (S, Option<S>) {
+    (self.primary, self.secondary)
+  }
+
+  pub(crate) fn map<T>(self, mut f: impl FnMut(S) -> io::Result<T>) -> io::Result<DualStack<T>> {
+    Ok(DualStack {
+      primary: f(self.primary)?,
+      secondary: self.secondary.map(f).transpose()?,
+      strategy: self.strategy,
+    })
+  }
+}
+
+impl<'a, S> IntoIterator for &'a DualStack<S> {
+  type Item = &'a S;
+  type IntoIter = core::iter::Chain<core::iter::Once<&'a S>, core::option::Iter<'a, S>>;
+
+  #[inline]
+  fn into_iter(self) -> Self::IntoIter {
+    core::iter::once(&self.primary).chain(self.secondary.iter())
+  }
+}
+
+/// Binds TCP listeners to the wildcard addresses on `port`, using the best
+/// strategy the system supports according to [`probe`]:
+///
+/// 1. a single `[::]` listener if IPv4-mapped IPv6 is understood,
+/// 2. otherwise separate `0.0.0.0` and `[::]` listeners,
+/// 3. otherwise a listener of whichever family is supported.
+///
+/// If `port` is `0` and two listeners are bound, both use the same port.
+/// The port the kernel picks for IPv4 may already be taken on IPv6, so a
+/// few ports are tried before failing with
+/// [`AddrInUse`](io::ErrorKind::AddrInUse).
+pub fn bind_any(port: u16) -> io::Result<DualStackListener> {
+  bind_wildcard(probe().tcp(), SocketType::STREAM, port)?.map(|fd| Ok(TcpListener::from(fd)))
+}
+
+/// Binds UDP sockets to the wildcard addresses on `port`, using the same
+/// strategies as [`bind_any`] according to the UDP capabilities.
+pub fn bind_any_udp(port: u16) -> io::Result<DualStackUdpSocket> {
+  bind_wildcard(probe().udp(), SocketType::DGRAM, port)?.map(|fd| Ok(UdpSocket::from(fd)))
+}
+
+/// The asynchronous version of [`bind_any`], which returns tokio listeners.
+///
+/// The capabilities are looked up with [`probe_async`](crate::probe_async).
+/// Must be called within a tokio runtime.
+#[cfg(feature = "tokio")]
+pub async fn bind_any_async(port: u16) -> io::Result<DualStack<tokio::net::TcpListener>> {
+  let caps = crate::probe_async().await.tcp();
+  bind_wildcard(caps, SocketType::STREAM, port)?.map(|fd| {
+    let listener = TcpListener::from(fd);
+    listener.set_nonblocking(true)?;
+    tokio::net::TcpListener::from_std(listener)
+  })
+}
+
+/// The asynchronous version of [`bind_any_udp`], which returns tokio
+/// sockets, see [`bind_any_async`].
+#[cfg(feature = "tokio")]
+pub async fn bind_any_udp_async(port: u16) -> io::Result<DualStack<tokio::net::UdpSocket>> {
+  let caps = crate::probe_async().await.udp();
+  bind_wildcard(caps, SocketType::DGRAM, port)?.map(|fd| {
+    let socket = UdpSocket::from(fd);
+    socket.set_nonblocking(true)?;
+    tokio::net::UdpSocket::from_std(socket)
+  })
+}
+
+pub(crate) fn bind_wildcard(
+  caps: TransportProbe,
+  ty: SocketType,
+  port: u16,
+) -> io::Result<DualStack<OwnedFd>> {
+  let strategy = BindStrategy::choose(caps).ok_or_else(|| {
+    io::Error::new(
+      io::ErrorKind::Unsupported,
+      "neither IPv4 nor IPv6 is supported",
+    )
+  })?;
+
+  let (primary, secondary) = match strategy {
+    BindStrategy::DualStack => (bind_socket(ty, Some(false), v6(port))?, None),
+    BindStrategy::Separate => {
+      let (primary, secondary) = bind_separate(ty, port)?;
+      (primary, Some(secondary))
+    }
+    BindStrategy::Ipv4Only => (bind_socket(ty, None, v4(port))?, None),
+    BindStrategy::Ipv6Only => (bind_socket(ty, Some(true), v6(port))?, None),
+  };
+
+  Ok(DualStack {
+    primary,
+    secondary,
+    strategy,
+  })
+}
+
+/// Binds `0.0.0.0` and `[::]` on the same port.
+fn bind_separate(ty: SocketType, port: u16) -> io::Result<(OwnedFd, OwnedFd)> {
+  let mut attempts = 1;
+  loop {
+    let primary = bind_socket(ty, None, v4(port))?;
+    let bound = SocketAddr::try_from(getsockname(&primary)?)
+      .map_err(|_| io::Error::from(io::ErrorKind::InvalidData))?
+      .port();
+    match bind_socket(ty, Some(true), v6(bound)) {
+      Ok(secondary) => return Ok((primary, secondary)),
+      Err(e)
+        if port == 0 && e.kind() == io::ErrorKind::AddrInUse && attempts < SEPARATE_ATTEMPTS =>
+      {
+        attempts += 1;
+      }
+      Err(e) => return Err(e),
+    }
+  }
+}
+
+#[inline]
+fn v4(port: u16) -> SocketAddr {
+  SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, port))
+}
+
+#[inline]
+fn v6(port: u16) -> SocketAddr {
+  SocketAddr::V6(SocketAddrV6::new(Ipv6Addr::UNSPECIFIED, port, 0, 0))
+}
+
+fn bind_socket(ty: SocketType, v6_only: Option<bool>, addr: SocketAddr) -> io::Result<OwnedFd> {
+  let family = match addr {
+    SocketAddr::V4(_) => AddressFamily::INET,
+    SocketAddr::V6(_) => AddressFamily::INET6,
+  };
+  let sock = new_socket(family, ty)?;
+
+  // Same as std, allows rebinding the port while old connections linger.
+  #[cfg(not(windows))]
+  if ty == SocketType::STREAM {
+    sockopt::set_socket_reuseaddr(&sock, true)?;
+  }
+
+  if let Some(v6_only) = v6_only {
+    sockopt::set_ipv6_v6only(&sock, v6_only)?;
+  }
+
+  bind(&sock, &addr)?;
+
+  if ty == SocketType::STREAM {
+    listen(&sock, BACKLOG)?;
+  }
+
+  Ok(sock)
+}
+
+#[cfg(not(any(
+  windows,
+  target_vendor = "apple",
+  target_os = "aix",
+  target_os = "haiku"
+)))]
+pub(crate) fn new_socket(family: AddressFamily, ty: SocketType) -> io::Result<OwnedFd> {
+  use rustix::net::{socket_with, SocketFlags};
+
+  socket_with(family, ty, SocketFlags::CLOEXEC, None).map_err(Into::into)
+}
+
+#[cfg(any(
+  windows,
+  target_vendor = "apple",
+  target_os = "aix",
+  target_os = "haiku"
+))]
+pub(crate) fn new_socket(family: AddressFamily, ty: SocketType) -> io::Result<OwnedFd> {
+  // The socket outlives this call, so Winsock is never cleaned up, like std does.
+  #[cfg(windows)]
+  let _ = rustix::net::wsa_startup();
+
+  let sock = rustix::net::socket(family, ty, None)?;
+
+  #[cfg(unix)]
+  rustix::io::fcntl_setfd(&sock, rustix::io::FdFlags::CLOEXEC)?;
+
+  Ok(sock)
+}
+
+#[test]
+fn test_choose() {
+  assert_eq!(
+    BindStrategy::choose(TransportProbe::new(true, true, true)),
+    Some(BindStrategy::DualStack)
+  );
+  assert_eq!(
+    BindStrategy::choose(TransportProbe::new(true, true, false)),
+    Some(BindStrategy::Separate)
+  );
+  assert_eq!(
+    BindStrategy::choose(TransportProbe::new(true, false, true)),
+    Some(BindStrategy::Ipv4Only)
+  );
+  assert_eq!(
+    BindStrategy::choose(TransportProbe::new(false, true, false)),
+    Some(BindStrategy::Ipv6Only)
+  );
+  assert_eq!(
+    BindStrategy::choose(TransportProbe::new(false, false, false)),
+    None
+  );
+}
+
+#[test]
+fn test_bind_any() {
+  let listener = bind_any(0).unwrap();
+  assert_eq!(
+    Some(listener.strategy()),
+    BindStrategy::choose(probe().tcp())
+  );
+
+  let port = listener.primary().local_addr().unwrap().port();
+  for sock in &listener {
+    assert_eq!(sock.local_addr().unwrap().port(), port);
+  }
+
+  if probe().ipv4() && crate::interfaces::loopback_up() {
+    std::net::TcpStream::connect((Ipv4Addr::LOCALHOST, port)).unwrap();
+  }
+
+  let socket = bind_any_udp(0).unwrap();
+  assert_eq!(Some(socket.strategy()), BindStrategy::choose(probe().udp()));
+}
+
+#[test]
+fn test_bind_separate() {
+  let separate = TransportProbe::new(true, true, false);
+  let Ok(taken) = bind_socket(SocketType::STREAM, Some(true), v6(0)) else {
+    return;
+  };
+  let port = SocketAddr::try_from(getsockname(&taken).unwrap())
+    .unwrap()
+    .port();
+
+  // An explicit port is never replaced.
+  let err = bind_wildcard(separate, SocketType::STREAM, port).unwrap_err();
+  assert_eq!(err.kind(), io::ErrorKind::AddrInUse);
+
+  if probe().ipv4() {
+    let listener = bind_wildcard(separate, SocketType::STREAM, 0).unwrap();
+    assert_eq!(listener.strategy(), BindStrategy::Separate);
+    assert!(listener.secondary().is_some());
+  }
+}
+
+#[cfg(all(test, feature = "tokio"))]
+#[tokio::test]
+async fn test_bind_any_async() {
+  let listener = bind_any_async(0).await.unwrap();
+  assert_eq!(
+    Some(listener.strategy()),
+    BindStrategy::choose(probe().tcp())
+  );
+
+  if probe().ipv4() && crate::interfaces::loopback_up() {
+    let port = listener.primary().local_addr().unwrap().port();
+    let (client, accepted) = tokio::join!(
+      tokio::net::TcpStream::connect((Ipv4Addr::LOCALHOST, port)),
+      listener.primary().accept()
+    );
+    assert_eq!(
+      client.unwrap().local_addr().unwrap().port(),
+      accepted.unwrap().1.port()
+    );
+  }
+
+  let socket = bind_any_udp_async(0).await.unwrap();
+  assert_eq!(Some(socket.strategy()), BindStrategy::choose(probe().udp()));
+}
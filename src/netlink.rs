@@ -0,0 +1,292 @@
+//! Minimal rtnetlink plumbing shared by the Linux specific modules.
+
+use std::io;
+
+use rustix::{
+  fd::OwnedFd,
+  io::Errno,
+  net::{
+    bind, netlink::SocketAddrNetlink, recv, send, socket_with, AddressFamily, RecvFlags, SendFlags,
+    SocketFlags, SocketType,
+  },
+};
+
+pub(crate) const RTMGRP_LINK: u32 = 0x1;
+pub(crate) const RTMGRP_IPV4_IFADDR: u32 = 0x10;
+pub(crate) const RTMGRP_IPV6_IFADDR: u32 = 0x100;
+
+const NLM_F_REQUEST: u16 = 0x1;
+const NLM_F_DUMP: u16 = 0x300;
+
+const NLMSG_ERROR: u16 = 2;
+const NLMSG_DONE: u16 = 3;
+pub(crate) const NLMSG_OVERRUN: u16 = 4;
+
+pub(crate) const RTM_NEWLINK: u16 = 16;
+pub(crate) const RTM_DELLINK: u16 = 17;
+pub(crate) const RTM_GETLINK: u16 = 18;
+pub(crate) const RTM_NEWADDR: u16 = 20;
+pub(crate) const RTM_DELADDR: u16 = 21;
+pub(crate) const RTM_GETADDR: u16 = 22;
+pub(crate) const RTM_NEWROUTE: u16 = 24;
+pub(crate) const RTM_GETROUTE: u16 = 26;
+
+const HEADER_LEN: usize = 16;
+const ATTR_HEADER_LEN: usize = 4;
+
+/// Masks out `NLA_F_NESTED` and `NLA_F_NET_BYTEORDER`.
+const ATTR_TYPE_MASK: u16 = 0x3fff;
+
+/// The kernel never sends dump datagrams larger than this.
+const DUMP_BUF_SIZE: usize = 32 * 1024;
+
+/// The sequence number of dump requests, each of which uses its own socket.
+const DUMP_SEQ: u32 = 1;
+
+/// Opens a `NETLINK_ROUTE` socket subscribed to the given multicast groups.
+pub(crate) fn open(groups: u32, flags: SocketFlags) -> io::Result<OwnedFd> {
+  let fd = socket_with(
+    AddressFamily::NETLINK,
+    SocketType::RAW,
+    SocketFlags::CLOEXEC | flags,
+    // `NETLINK_ROUTE` is the default protocol.
+    None,
+  )?;
+  bind(&fd, &SocketAddrNetlink::new(0, groups))?;
+  Ok(fd)
+}
+
+/// Reads all pending datagrams from a non-blocking read of `fd` and returns
+/// `true` if any of them matched `relevant`.
+///
+/// Lost messages (`ENOBUFS`) count as relevant, because we cannot know
+/// what they were.
+pub(crate) fn drain(
+  fd: &OwnedFd,
+  buf: &mut [u8],
+  relevant: impl Fn(&Header) -> bool,
+) -> io::Result<bool> {
+  let mut found = false;
+  loop {
+    match recv(fd, &mut *buf, RecvFlags::DONTWAIT) {
+      Ok((n, _)) => found |= Messages::new(&buf[..n]).any(|(hdr, _)| relevant(&hdr)),
+      Err(Errno::AGAIN) => return Ok(found),
+      Err(Errno::NOBUFS) => found = true,
+      Err(Errno::INTR) => {}
+      Err(e) => return Err(e.into()),
+    }
+  }
+}
+
+/// Blocks until at least one datagram is readable from `fd`, and returns
+/// `true` if any message in it matched `relevant`.
+pub(crate) fn recv_blocking(
+  fd: &OwnedFd,
+  buf: &mut [u8],
+  relevant: impl Fn(&Header) -> bool,
+) -> io::Result<bool> {
+  loop {
+    match recv(fd, &mut *buf, RecvFlags::empty()) {
+      Ok((n, _)) => return Ok(Messages::new(&buf[..n]).any(|(hdr, _)| relevant(&hdr))),
+      Err(Errno::NOBUFS) => return Ok(true),
+      Err(Errno::INTR) => {}
+      Err(e) => return Err(e.into()),
+    }
+  }
+}
+
+/// Requests a dump of `ty` objects with the request `payload`, and calls `f`
+/// with the header and payload of every message of the reply.
+pub(crate) fn dump(ty: u16, payload: &[u8], mut f: impl FnMut(&Header, &[u8])) -> io::Result<()> {
+  let fd = open(0, SocketFlags::empty())?;
+  send(
+    &fd,
+    &encode(ty, NLM_F_REQUEST | NLM_F_DUMP, DUMP_SEQ, payload),
+    SendFlags::empty(),
+  )?;
+
+  let mut buf = vec![0; DUMP_BUF_SIZE];
+  loop {
+    let n = match recv(&fd, &mut buf[..], RecvFlags::empty()) {
+      Ok((n, _)) => n,
+      Err(Errno::INTR) => continue,
+      Err(e) => return Err(e.into()),
+    };
+
+    for (hdr, payload) in Messages::new(&buf[..n]) {
+      if hdr.seq != DUMP_SEQ {
+        continue;
+      }
+
+      match hdr.ty {
+        NLMSG_DONE => return Ok(()),
+        NLMSG_ERROR => {
+          // `struct nlmsgerr` starts with the negated errno, `0` is an ack.
+          let err = payload
+            .get(..4)
+            .map_or(0, |err| i32::from_ne_bytes(err.try_into().unwrap()));
+          if err != 0 {
+            return Err(io::Error::from_raw_os_error(-err));
+          }
+        }
+        _ => f(&hdr, payload),
+      }
+    }
+  }
+}
+
+/// Encodes a netlink message.
+fn encode(ty: u16, flags: u16, seq: u32, payload: &[u8]) -> Vec<u8> {
+  let len = HEADER_LEN + payload.len();
+  let mut buf = Vec::with_capacity(align(len));
+  buf.extend_from_slice(&(len as u32).to_ne_bytes());
+  buf.extend_from_slice(&ty.to_ne_bytes());
+  buf.extend_from_slice(&flags.to_ne_bytes());
+  buf.extend_from_slice(&seq.to_ne_bytes());
+  // The port id, `0` lets the kernel fill it in.
+  buf.extend_from_slice(&0u32.to_ne_bytes());
+  buf.extend_from_slice(payload);
+  buf.resize(align(len), 0);
+  buf
+}
+
+/// The fixed part of a `struct nlmsghdr`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub(crate) struct Header {
+  pub(crate) ty: u16,
+  pub(crate) seq: u32,
+}
+
+/// Iterates over the netlink messages in a datagram.
+pub(crate) struct Messages<'a> {
+  buf: &'a [u8],
+}
+
+impl<'a> Messages<'a> {
+  #[inline]
+  pub(crate) const fn new(buf: &'a [u8]) -> Self {
+    Self { buf }
+  }
+}
+
+impl<'a> Iterator for Messages<'a> {
+  type Item = (Header, &'a [u8]);
+
+  fn next(&mut self) -> Option<Self::Item> {
+    if self.buf.len() < HEADER_LEN {
+      return None;
+    }
+
+    let len = u32::from_ne_bytes(self.buf[0..4].try_into().unwrap()) as usize;
+    if len < HEADER_LEN || len > self.buf.len() {
+      self.buf = &[];
+      return None;
+    }
+
+    let hdr = Header {
+      ty: u16::from_ne_bytes(self.buf[4..6].try_into().unwrap()),
+      seq: u32::from_ne_bytes(self.buf[8..12].try_into().unwrap()),
+    };
+    let payload = &self.buf[HEADER_LEN..len];
+    self.buf = &self.buf[align(len).min(self.buf.len())..];
+    Some((hdr, payload))
+  }
+}
+
+/// Iterates over the `struct rtattr` attributes of a message, yielding
+/// their types and payloads.
+pub(crate) struct Attributes<'a> {
+  buf: &'a [u8],
+}
+
+impl<'a> Attributes<'a> {
+  #[inline]
+  pub(crate) const fn new(buf: &'a [u8]) -> Self {
+    Self { buf }
+  }
+}
+
+impl<'a> Iterator for Attributes<'a> {
+  type Item = (u16, &'a [u8]);
+
+  fn next(&mut self) -> Option<Self::Item> {
+    if self.buf.len() < ATTR_HEADER_LEN {
+      return None;
+    }
+
+    let len = u16::from_ne_bytes(self.buf[0..2].try_into().unwrap()) as usize;
+    if len < ATTR_HEADER_LEN || len > self.buf.len() {
+      self.buf = &[];
+      return None;
+    }
+
+    let ty = u16::from_ne_bytes(self.buf[2..4].try_into().unwrap()) & ATTR_TYPE_MASK;
+    let payload = &self.buf[ATTR_HEADER_LEN..len];
+    self.buf = &self.buf[align(len).min(self.buf.len())..];
+    Some((ty, payload))
+  }
+}
+
+/// `NLMSG_ALIGN`
+#[inline]
+pub(crate) const fn align(len: usize) -> usize {
+  (len + 3) & !3
+}
+
+/// Returns the value of a test's netlink query, or `None` if netlink
+/// sockets are forbidden, as they may be in sandboxed environments.
+#[cfg(test)]
+pub(crate) fn allowed<T>(result: io::Result<T>) -> Option<T> {
+  match result {
+    Ok(value) => Some(value),
+    Err(e)
+      if e.kind() == io::ErrorKind::PermissionDenied
+        || e.raw_os_error() == Some(Errno::AFNOSUPPORT.raw_os_error()) =>
+    {
+      None
+    }
+    Err(e) => panic!("netlink query failed: {e}"),
+  }
+}
+
+#[cfg(test)]
+pub(crate) fn message(ty: u16, payload: &[u8]) -> Vec<u8> {
+  encode(ty, 0, 0, payload)
+}
+
+#[cfg(test)]
+pub(crate) fn attribute(ty: u16, payload: &[u8]) -> Vec<u8> {
+  let len = ATTR_HEADER_LEN + payload.len();
+  let mut buf = Vec::with_capacity(align(len));
+  buf.extend_from_slice(&(len as u16).to_ne_bytes());
+  buf.extend_from_slice(&ty.to_ne_bytes());
+  buf.extend_from_slice(payload);
+  buf.resize(align(len), 0);
+  buf
+}
+
+#[test]
+fn test_messages() {
+  let mut buf = message(RTM_NEWADDR, &[1, 2, 3]);
+  buf.extend(message(RTM_DELLINK, &[]));
+
+  let msgs = Messages::new(&buf).collect::<Vec<_>>();
+  assert_eq!(msgs.len(), 2);
+  assert_eq!(msgs[0].0.ty, RTM_NEWADDR);
+  assert_eq!(msgs[0].1, &[1, 2, 3]);
+  assert_eq!(msgs[1].0.ty, RTM_DELLINK);
+  assert!(msgs[1].1.is_empty());
+
+  // truncated datagrams stop the iteration
+  assert_eq!(Messages::new(&buf[..10]).count(), 0);
+}
+
+#[test]
+fn test_attributes() {
+  let mut buf = attribute(3, b"lo\0");
+  buf.extend(attribute(8 | 0x8000, &[]));
+
+  let attrs = Attributes::new(&buf).collect::<Vec<_>>();
+  assert_eq!(attrs, [(3, &b"lo\0"[..]), (8, &[][..])]);
+  assert_eq!(Attributes::new(&buf[..3]).count(), 0);
+}
@@ -0,0 +1,260 @@
+use core::fmt;
+use std::{
+  env,
+  sync::{OnceLock, PoisonError, RwLock},
+};
+
+use super::{Probe, TransportProbe};
+
+/// Forces IPv4 support, e.g. `IPROBE_IPV4=0`.
+const IPV4_VAR: &str = "IPROBE_IPV4";
+/// Forces IPv6 support, e.g. `IPROBE_IPV6=1`.
+const IPV6_VAR: &str = "IPROBE_IPV6";
+/// Forces IPv4-mapped IPv6 support, e.g. `IPROBE_IPV4_MAPPED=0`.
+const IPV4_MAPPED_VAR: &str = "IPROBE_IPV4_MAPPED";
+
+static OVERRIDE: RwLock<Option<Probe>> = RwLock::new(None);
+static ENV: OnceLock<Overrides> = OnceLock::new();
+
+/// The capabilities forced by [`set_override`] or the environment, instead
+/// of being probed.
+///
+/// The environment variables `IPROBE_IPV4`, `IPROBE_IPV6` and
+/// `IPROBE_IPV4_MAPPED` force a family for both TCP and UDP when set to
+/// `1`/`true` or `0`/`false`. They are read once, on first use. An override
+/// set with [`set_override`] replaces all results, including the ones of
+/// the environment.
+///
+/// The overrides apply to [`probe`](crate::probe) and the accessors of its
+/// cached result only, the reports and [`probe_fresh`](crate::probe_fresh)
+/// always contain the results of the checks. The reports record the
+/// overrides in effect, see
+/// [`ProbeReport::overrides`](crate::ProbeReport::overrides).
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Overrides {
+  probe: Option<Probe>,
+  ipv4: Option<bool>,
+  ipv6: Option<bool>,
+  ipv4_mapped_ipv6: Option<bool>,
+}
+
+impl Overrides {
+  /// No overrides, like [`Overrides::default`].
+  pub(crate) const NONE: Self = Self {
+    probe: None,
+    ipv4: None,
+    ipv6: None,
+    ipv4_mapped_ipv6: None,
+  };
+
+  /// Returns the capabilities set with [`set_override`], if any.
+  #[inline]
+  pub const fn probe(&self) -> Option<Probe> {
+    self.probe
+  }
+
+  /// Returns the IPv4 support forced by `IPROBE_IPV4`, if any.
+  #[inline]
+  pub const fn ipv4(&self) -> Option<bool> {
+    self.ipv4
+  }
+
+  /// Returns the IPv6 support forced by `IPROBE_IPV6`, if any.
+  #[inline]
+  pub const fn ipv6(&self) -> Option<bool> {
+    self.ipv6
+  }
+
+  /// Returns the IPv4-mapped IPv6 support forced by `IPROBE_IPV4_MAPPED`,
+  /// if any.
+  #[inline]
+  pub const fn ipv4_mapped_ipv6(&self) -> Option<bool> {
+    self.ipv4_mapped_ipv6
+  }
+
+  /// Returns `true` if nothing is overridden.
+  #[inline]
+  pub const fn is_empty(&self) -> bool {
+    self.probe.is_none()
+      && self.ipv4.is_none()
+      && self.ipv6.is_none()
+      && self.ipv4_mapped_ipv6.is_none()
+  }
+
+  /// Returns the probed capabilities with the overrides applied.
+  #[inline]
+  pub const fn apply(&self, probe: Probe) -> Probe {
+    if let Some(probe) = self.probe {
+      return probe;
+    }
+
+    Probe {
+      tcp: self.apply_transport(probe.tcp),
+      udp: self.apply_transport(probe.udp),
+    }
+  }
+
+  /// Writes the environment variables which are set, e.g. `IPROBE_IPV6=0`.
+  fn write_vars(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let vars = [
+      (IPV4_VAR, self.ipv4),
+      (IPV6_VAR, self.ipv6),
+      (IPV4_MAPPED_VAR, self.ipv4_mapped_ipv6),
+    ];
+    let set = vars
+      .iter()
+      .filter_map(|(var, value)| Some((var, u8::from((*value)?))));
+    for (i, (var, value)) in set.enumerate() {
+      if i > 0 {
+        f.write_str(", ")?;
+      }
+      write!(f, "{var}={value}")?;
+    }
+    Ok(())
+  }
+
+  const fn apply_transport(&self, caps: TransportProbe) -> TransportProbe {
+    const fn or(value: Option<bool>, probed: bool) -> bool {
+      match value {
+        Some(value) => value,
+        None => probed,
+      }
+    }
+
+    TransportProbe {
+      ipv4: or(self.ipv4, caps.ipv4),
+      ipv6: or(self.ipv6, caps.ipv6),
+      ipv4_mapped_ipv6: or(self.ipv4_mapped_ipv6, caps.ipv4_mapped_ipv6),
+    }
+  }
+}
+
+impl fmt::Display for Overrides {
+  /// Writes what is forced, e.g. `IPROBE_IPV6=0` or `set_override(IPv4)`,
+  /// or `none`.
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self.probe {
+      Some(probe) => write!(f, "set_override({probe})"),
+      None if self.is_empty() => f.write_str("none"),
+      None => self.write_vars(f),
+    }
+  }
+}
+
+/// Forces [`probe`](crate::probe) and the accessors of its cached result to
+/// return `probe` instead of the probed capabilities, until
+/// [`clear_override`] is called.
+///
+/// Returns the previous override. Meant for exercising the code paths of
+/// other hosts in tests, see [`scoped_override`] to restore the previous
+/// override automatically.
+pub fn set_override(probe: Probe) -> Option<Probe> {
+  OVERRIDE
+    .write()
+    .unwrap_or_else(PoisonError::into_inner)
+    .replace(probe)
+}
+
+/// Removes the override set with [`set_override`], returns it.
+pub fn clear_override() -> Option<Probe> {
+  OVERRIDE
+    .write()
+    .unwrap_or_else(PoisonError::into_inner)
+    .take()
+}
+
+/// Sets an override like [`set_override`], which is restored to the
+/// previous one when the returned guard is dropped.
+///
+/// The override is global, so tests running in parallel observe it too.
+pub fn scoped_override(probe: Probe) -> OverrideGuard {
+  OverrideGuard {
+    previous: set_override(probe),
+  }
+}
+
+/// Restores the previous override when dropped, see [`scoped_override`].
+#[derive(Debug)]
+#[must_use = "the override is restored immediately if the guard is dropped"]
+pub struct OverrideGuard {
+  previous: Option<Probe>,
+}
+
+impl Drop for OverrideGuard {
+  fn drop(&mut self) {
+    *OVERRIDE.write().unwrap_or_else(PoisonError::into_inner) = self.previous;
+  }
+}
+
+/// Returns the overrides currently in effect.
+pub fn overrides() -> Overrides {
+  let env = *ENV.get_or_init(|| from_env(|name| env::var(name).ok()));
+  Overrides {
+    probe: *OVERRIDE.read().unwrap_or_else(PoisonError::into_inner),
+    ..env
+  }
+}
+
+fn from_env(var: impl Fn(&str) -> Option<String>) -> Overrides {
+  let flag = |name| match var(name)?.trim() {
+    "1" | "true" => Some(true),
+    "0" | "false" => Some(false),
+    _ => None,
+  };
+
+  Overrides {
+    probe: None,
+    ipv4: flag(IPV4_VAR),
+    ipv6: flag(IPV6_VAR),
+    ipv4_mapped_ipv6: flag(IPV4_MAPPED_VAR),
+  }
+}
+
+#[test]
+fn test_overrides() {
+  let probed = Probe::new(
+    TransportProbe::new(true, true, true),
+    TransportProbe::new(true, false, false),
+  );
+
+  let overrides = from_env(|name| match name {
+    IPV6_VAR => Some("0".to_string()),
+    IPV4_MAPPED_VAR => Some("yes".to_string()),
+    _ => None,
+  });
+  assert_eq!(overrides.ipv6(), Some(false));
+  assert_eq!(overrides.ipv4_mapped_ipv6(), None);
+  let probe = overrides.apply(probed);
+  assert!(probe.ipv4() && !probe.ipv6() && probe.ipv4_mapped_ipv6());
+  assert!(!probe.udp().ipv6());
+
+  let forced = Probe::new(probed.udp, probed.udp);
+  let overrides = Overrides {
+    probe: Some(forced),
+    ..overrides
+  };
+  assert_eq!(overrides.apply(probed), forced);
+  assert_eq!(overrides.to_string(), "set_override(IPv4, UDP IPv4)");
+  assert!(Overrides::default().is_empty());
+  assert_eq!(Overrides::default().apply(probed), probed);
+  assert_eq!(Overrides::default().to_string(), "none");
+
+  let overrides = from_env(|name| match name {
+    IPV4_VAR => Some("true".to_string()),
+    IPV6_VAR => Some("0".to_string()),
+    _ => None,
+  });
+  assert_eq!(overrides.to_string(), "IPROBE_IPV4=1, IPROBE_IPV6=0");
+
+  let mut report = crate::ProbeBuilder::new()
+    .with_tcp(false)
+    .with_udp(false)
+    .report();
+  report.overrides = overrides;
+  assert_eq!(report.overrides(), overrides);
+  assert_eq!(
+    report.to_string().lines().last(),
+    Some("overrides: IPROBE_IPV4=1, IPROBE_IPV6=0")
+  );
+}
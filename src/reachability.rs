@@ -0,0 +1,207 @@
+use core::fmt;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+use super::{address_selection::source_address_for, interfaces, Probe};
+
+/// A well-known global IPv4 address used to look up the route to the
+/// internet, no packet is ever sent to it.
+const GLOBAL_IPV4: IpAddr = IpAddr::V4(Ipv4Addr::new(8, 8, 8, 8));
+
+/// A well-known global IPv6 address used to look up the route to the
+/// internet, no packet is ever sent to it.
+const GLOBAL_IPV6: IpAddr = IpAddr::V6(Ipv6Addr::new(0x2001, 0x4860, 0x4860, 0, 0, 0, 0, 0x8888));
+
+/// How far the host can communicate over an address family, ordered from
+/// the least to the most reachable.
+///
+/// See [`Probe::ipv6_reachability`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+#[non_exhaustive]
+pub enum Reachability {
+  /// The family is not supported.
+  None,
+  /// Only the loopback address works.
+  LoopbackOnly,
+  /// Link-local addresses, e.g. `fe80::/10` or `169.254.0.0/16`, reach the
+  /// neighbours on the same link.
+  LinkLocal,
+  /// Private addresses, e.g. unique local IPv6 addresses in `fc00::/7` or
+  /// `10.0.0.0/8`, reach the hosts of the same site.
+  SiteLocal,
+  /// Global addresses with a route to the internet.
+  Global,
+}
+
+impl Reachability {
+  /// Returns the reachability an address gives on its own.
+  ///
+  /// Returns `None` for the unspecified and multicast addresses.
+  pub fn of(addr: IpAddr) -> Self {
+    match addr {
+      IpAddr::V4(addr) => Self::of_ipv4(addr),
+      IpAddr::V6(addr) => match addr.to_ipv4_mapped() {
+        Some(addr) => Self::of_ipv4(addr),
+        None => Self::of_ipv6(addr),
+      },
+    }
+  }
+
+  fn of_ipv4(addr: Ipv4Addr) -> Self {
+    let [a, b, ..] = addr.octets();
+    if addr.is_unspecified() || addr.is_multicast() || addr.is_broadcast() {
+      Self::None
+    } else if addr.is_loopback() {
+      Self::LoopbackOnly
+    } else if addr.is_link_local() {
+      Self::LinkLocal
+    } else if addr.is_private() || (a == 100 && b & 0xc0 == 64) {
+      // RFC 1918 and the shared address space of carrier-grade NATs.
+      Self::SiteLocal
+    } else {
+      Self::Global
+    }
+  }
+
+  fn of_ipv6(addr: Ipv6Addr) -> Self {
+    let first = addr.segments()[0];
+    if addr.is_unspecified() || addr.is_multicast() {
+      Self::None
+    } else if addr.is_loopback() {
+      Self::LoopbackOnly
+    } else if first & 0xffc0 == 0xfe80 {
+      Self::LinkLocal
+    } else if first & 0xfe00 == 0xfc00 || first & 0xffc0 == 0xfec0 {
+      Self::SiteLocal
+    } else {
+      Self::Global
+    }
+  }
+
+  /// Returns a short human readable description of the reachability.
+  #[inline]
+  pub const fn as_str(&self) -> &'static str {
+    match self {
+      Self::None => "none",
+      Self::LoopbackOnly => "loopback only",
+      Self::LinkLocal => "link-local",
+      Self::SiteLocal => "site-local",
+      Self::Global => "global",
+    }
+  }
+}
+
+impl fmt::Display for Reachability {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.write_str(self.as_str())
+  }
+}
+
+impl Probe {
+  /// Classifies how far the host can communicate over IPv4, see
+  /// [`Probe::ipv6_reachability`].
+  pub fn ipv4_reachability(&self) -> Reachability {
+    reachability(self.ipv4(), GLOBAL_IPV4)
+  }
+
+  /// Classifies how far the host can communicate over IPv6.
+  ///
+  /// The addresses of the interfaces which are up are classified, and the
+  /// most reachable one wins. Global addresses only count as
+  /// [`Reachability::Global`] if the routing table has a route to the
+  /// internet which uses a global source address, otherwise they count as
+  /// [`Reachability::SiteLocal`]. No packet is sent.
+  ///
+  /// Where [`interfaces`] is not available, only the
+  /// route is looked at. The result is not cached.
+  pub fn ipv6_reachability(&self) -> Reachability {
+    reachability(self.ipv6(), GLOBAL_IPV6)
+  }
+}
+
+fn reachability(supported: bool, global: IpAddr) -> Reachability {
+  if !supported {
+    return Reachability::None;
+  }
+
+  let addrs = interfaces().ok().map(|ifaces| {
+    ifaces
+      .into_iter()
+      .filter(|iface| iface.is_up() && iface.is_running())
+      .flat_map(|iface| iface.addrs().to_vec())
+      .filter(|addr| !addr.is_tentative() && addr.addr().is_ipv4() == global.is_ipv4())
+      .map(|addr| addr.addr())
+      .collect::<Vec<_>>()
+  });
+  classify(addrs.as_deref(), source_address_for(global).ok())
+}
+
+/// Classifies a supported family from its local addresses, if known, and
+/// the source address of the route to the internet, if any.
+fn classify(addrs: Option<&[IpAddr]>, route_source: Option<IpAddr>) -> Reachability {
+  let route = route_source.map_or(Reachability::None, Reachability::of);
+  let Some(addrs) = addrs else {
+    return route.max(Reachability::LoopbackOnly);
+  };
+
+  let best = addrs
+    .iter()
+    .map(|addr| Reachability::of(*addr))
+    .max()
+    .unwrap_or(Reachability::None)
+    .max(Reachability::LoopbackOnly);
+  if best == Reachability::Global && route != Reachability::Global {
+    Reachability::SiteLocal
+  } else {
+    best
+  }
+}
+
+#[test]
+fn test_classify() {
+  let ip = |s: &str| s.parse::<IpAddr>().unwrap();
+  let check = |addrs: &[&str], route: Option<&str>| {
+    let addrs: Vec<_> = addrs.iter().map(|addr| ip(addr)).collect();
+    classify(Some(&addrs), route.map(ip))
+  };
+
+  assert_eq!(check(&[], None), Reachability::LoopbackOnly);
+  assert_eq!(check(&["::1"], None), Reachability::LoopbackOnly);
+  assert_eq!(check(&["::1", "fe80::1"], None), Reachability::LinkLocal);
+  assert_eq!(
+    check(&["fe80::1", "fd00::2"], Some("fd00::2")),
+    Reachability::SiteLocal
+  );
+  assert_eq!(
+    check(&["fe80::1", "2001:db8::2"], Some("2001:db8::2")),
+    Reachability::Global
+  );
+  // A global address without a route to the internet.
+  assert_eq!(
+    check(&["fe80::1", "2001:db8::2"], None),
+    Reachability::SiteLocal
+  );
+  assert_eq!(
+    check(&["127.0.0.1", "192.168.1.2"], Some("192.168.1.2")),
+    Reachability::SiteLocal
+  );
+  assert_eq!(
+    classify(None, Some(ip("100.64.0.1"))),
+    Reachability::SiteLocal
+  );
+  assert_eq!(classify(None, None), Reachability::LoopbackOnly);
+}
+
+#[test]
+fn test_reachability() {
+  let probe = crate::probe();
+  assert_eq!(
+    probe.ipv4_reachability() == Reachability::None,
+    !probe.ipv4()
+  );
+  assert_eq!(
+    probe.ipv6_reachability() == Reachability::None,
+    !probe.ipv6()
+  );
+}
@@ -0,0 +1,520 @@
+use core::fmt;
+use std::net::SocketAddr;
+
+use rustix::io::Errno;
+
+use super::{
+  connectivity::is_ipv4_mapped, ConnectivityReport, Overrides, Probe, TransportProbe, V6OnlyReport,
+};
+
+#[cfg(target_os = "linux")]
+use super::Ipv6Sysctls;
+
+/// The step of a check at which a probe failed.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+#[non_exhaustive]
+pub enum Step {
+  /// Creating the socket.
+  Socket,
+  /// Setting the `IPV6_V6ONLY` socket option.
+  #[cfg_attr(feature = "serde", serde(rename = "set_ipv6_v6only"))]
+  SetIpv6V6Only,
+  /// Reading the `IPV6_V6ONLY` socket option.
+  #[cfg_attr(feature = "serde", serde(rename = "get_ipv6_v6only"))]
+  GetIpv6V6Only,
+  /// Binding the socket to the probe address.
+  Bind,
+  /// Listening on the bound socket.
+  Listen,
+  /// Connecting to the listener.
+  Connect,
+  /// Accepting the connection.
+  Accept,
+  /// Sending a byte over the connection.
+  Send,
+  /// Receiving the byte on the accepted connection.
+  Recv,
+}
+
+impl Step {
+  /// Returns the name of the system call or socket option of this step.
+  #[inline]
+  pub const fn as_str(&self) -> &'static str {
+    match self {
+      Self::Socket => "socket",
+      Self::SetIpv6V6Only => "set_ipv6_v6only",
+      Self::GetIpv6V6Only => "get_ipv6_v6only",
+      Self::Bind => "bind",
+      Self::Listen => "listen",
+      Self::Connect => "connect",
+      Self::Accept => "accept",
+      Self::Send => "send",
+      Self::Recv => "recv",
+    }
+  }
+}
+
+impl fmt::Display for Step {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.write_str(self.as_str())
+  }
+}
+
+/// The classified reason of a probe failure.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+#[non_exhaustive]
+pub enum Reason {
+  /// The kernel does not support the address family or protocol,
+  /// e.g. `EAFNOSUPPORT` or `EPROTONOSUPPORT`.
+  Unsupported,
+  /// The operation was denied by a security policy such as seccomp,
+  /// e.g. `EPERM` or `EACCES`.
+  Denied,
+  /// The probe address is not configured on the host, e.g. `EADDRNOTAVAIL`.
+  AddressNotAvailable,
+  /// The process or the system ran out of resources,
+  /// e.g. `EMFILE` or `ENOBUFS`.
+  ResourceExhausted,
+  /// Any other error.
+  Other,
+}
+
+impl Reason {
+  /// Classifies an [`Errno`].
+  pub fn from_errno(errno: Errno) -> Self {
+    match errno {
+      Errno::AFNOSUPPORT | Errno::PROTONOSUPPORT | Errno::PFNOSUPPORT => Self::Unsupported,
+      Errno::ACCESS => Self::Denied,
+      #[cfg(not(windows))]
+      Errno::PERM => Self::Denied,
+      Errno::ADDRNOTAVAIL => Self::AddressNotAvailable,
+      Errno::MFILE | Errno::NOBUFS => Self::ResourceExhausted,
+      #[cfg(not(windows))]
+      Errno::NFILE | Errno::NOMEM => Self::ResourceExhausted,
+      _ => Self::Other,
+    }
+  }
+
+  /// Returns a short human readable description of the reason.
+  #[inline]
+  pub const fn as_str(&self) -> &'static str {
+    match self {
+      Self::Unsupported => "not supported by the kernel",
+      Self::Denied => "denied by policy",
+      Self::AddressNotAvailable => "address not available",
+      Self::ResourceExhausted => "resources exhausted",
+      Self::Other => "other error",
+    }
+  }
+}
+
+impl fmt::Display for Reason {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.write_str(self.as_str())
+  }
+}
+
+/// Describes why a single check of a probe failed.
+///
+/// The serde implementations are in the `snapshot` module.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct ProbeError {
+  step: Step,
+  errno: Errno,
+  addr: Option<SocketAddr>,
+}
+
+impl ProbeError {
+  #[inline]
+  pub(crate) const fn new(step: Step, errno: Errno, addr: Option<SocketAddr>) -> Self {
+    Self { step, errno, addr }
+  }
+
+  /// Returns the step at which the check failed.
+  #[inline]
+  pub const fn step(&self) -> Step {
+    self.step
+  }
+
+  /// Returns the underlying error code.
+  #[inline]
+  pub const fn errno(&self) -> Errno {
+    self.errno
+  }
+
+  /// Returns the address the failed step operated on, if any.
+  #[inline]
+  pub const fn address(&self) -> Option<SocketAddr> {
+    self.addr
+  }
+
+  /// Returns the classified reason of the failure.
+  #[inline]
+  pub fn reason(&self) -> Reason {
+    Reason::from_errno(self.errno)
+  }
+}
+
+impl fmt::Display for ProbeError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self.addr {
+      Some(addr) => write!(f, "{} {} failed", self.step, addr.ip())?,
+      None => write!(f, "{} failed", self.step)?,
+    }
+    write!(f, " ({}): {}", self.reason(), self.errno)
+  }
+}
+
+impl std::error::Error for ProbeError {}
+
+/// A detailed result of probing, which explains why each check failed.
+///
+/// See [`try_probe`](crate::try_probe).
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ProbeReport {
+  pub(crate) tcp: Option<TransportReport>,
+  pub(crate) udp: Option<TransportReport>,
+  pub(crate) connectivity: Option<ConnectivityReport>,
+  #[cfg_attr(feature = "serde", serde(with = "crate::snapshot::result::option"))]
+  pub(crate) dual_stack_listener: Option<Result<SocketAddr, ProbeError>>,
+  pub(crate) v6only: Option<V6OnlyReport>,
+  #[cfg_attr(feature = "serde", serde(default))]
+  pub(crate) overrides: Overrides,
+  #[cfg(target_os = "linux")]
+  pub(crate) ipv6_sysctls: Option<Ipv6Sysctls>,
+}
+
+impl ProbeReport {
+  /// Returns the result of the TCP IPv4 check, if TCP was checked.
+  #[inline]
+  pub const fn ipv4(&self) -> Option<Result<(), ProbeError>> {
+    match self.tcp {
+      Some(tcp) => Some(tcp.ipv4),
+      None => None,
+    }
+  }
+
+  /// Returns the result of the TCP IPv6 check, if TCP was checked.
+  #[inline]
+  pub const fn ipv6(&self) -> Option<Result<(), ProbeError>> {
+    match self.tcp {
+      Some(tcp) => Some(tcp.ipv6),
+      None => None,
+    }
+  }
+
+  /// Returns the result of the TCP IPv4-mapped IPv6 check, if TCP was checked.
+  #[inline]
+  pub const fn ipv4_mapped_ipv6(&self) -> Option<Result<(), ProbeError>> {
+    match self.tcp {
+      Some(tcp) => Some(tcp.ipv4_mapped_ipv6),
+      None => None,
+    }
+  }
+
+  /// Returns the results of the TCP stream socket checks, if they were run.
+  #[inline]
+  pub const fn tcp(&self) -> Option<TransportReport> {
+    self.tcp
+  }
+
+  /// Returns the results of the UDP datagram socket checks, if they were run.
+  #[inline]
+  pub const fn udp(&self) -> Option<TransportReport> {
+    self.udp
+  }
+
+  /// Returns the results of the end-to-end loopback connectivity checks,
+  /// if they were run.
+  ///
+  /// See [`ProbeBuilder::with_deep`](crate::ProbeBuilder::with_deep).
+  #[inline]
+  pub const fn connectivity(&self) -> Option<ConnectivityReport> {
+    self.connectivity
+  }
+
+  /// Returns the result of the dual-stack listener check, if it was run,
+  /// which is the address of the IPv4 client as seen by an `[::]` listener.
+  ///
+  /// See [`try_dual_stack_listener`](crate::try_dual_stack_listener).
+  #[inline]
+  pub const fn dual_stack_listener(&self) -> Option<Result<SocketAddr, ProbeError>> {
+    self.dual_stack_listener
+  }
+
+  /// Returns `true` if the dual-stack listener check was run, and an `[::]`
+  /// listener accepted an IPv4 client as an IPv4-mapped IPv6 address.
+  #[inline]
+  pub fn dual_stack_listener_works(&self) -> bool {
+    matches!(self.dual_stack_listener, Some(Ok(peer)) if is_ipv4_mapped(peer))
+  }
+
+  /// Returns the default `IPV6_V6ONLY` behaviour of new IPv6 sockets, if it
+  /// was checked.
+  ///
+  /// See [`ProbeBuilder::with_v6only`](crate::ProbeBuilder::with_v6only).
+  #[inline]
+  pub const fn v6only(&self) -> Option<V6OnlyReport> {
+    self.v6only
+  }
+
+  /// Returns the overrides which were in effect when the report was made,
+  /// and which [`probe`](crate::probe) applies on top of the results.
+  ///
+  /// The results of the report are never overridden. Reports of backends
+  /// which do not operate on the host have no overrides, see
+  /// [`SocketBackend::SYSTEM`](crate::SocketBackend::SYSTEM).
+  #[inline]
+  pub const fn overrides(&self) -> Overrides {
+    self.overrides
+  }
+
+  /// Returns the IPv6 related sysctls read while probing, which explain
+  /// why the IPv6 checks failed, or `None` if they could not be read or
+  /// the checks did not run on the host, see
+  /// [`SocketBackend`](crate::SocketBackend).
+  #[cfg(target_os = "linux")]
+  #[cfg_attr(docsrs, doc(cfg(target_os = "linux")))]
+  #[inline]
+  pub const fn ipv6_sysctls(&self) -> Option<&Ipv6Sysctls> {
+    self.ipv6_sysctls.as_ref()
+  }
+
+  /// Returns the capabilities summarized by this report.
+  ///
+  /// Transports which were not checked are reported as unsupported. The
+  /// results of the connectivity and dual-stack listener checks are not
+  /// taken into account, and neither are the
+  /// [`overrides`](ProbeReport::overrides).
+  #[inline]
+  pub const fn probe(&self) -> Probe {
+    const NONE: TransportProbe = TransportProbe {
+      ipv4: false,
+      ipv6: false,
+      ipv4_mapped_ipv6: false,
+    };
+
+    Probe {
+      tcp: match self.tcp {
+        Some(tcp) => tcp.probe(),
+        None => NONE,
+      },
+      udp: match self.udp {
+        Some(udp) => udp.probe(),
+        None => NONE,
+      },
+    }
+  }
+}
+
+impl fmt::Display for ProbeReport {
+  /// Writes one line per check which was run, e.g.
+  /// `IPv6: unavailable: bind ::1 failed (...)`.
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let mut lines = Lines::new(f);
+    if let Some(tcp) = self.tcp {
+      tcp.write_checks(&mut lines, "")?;
+    }
+    if let Some(udp) = self.udp {
+      udp.write_checks(&mut lines, "UDP ")?;
+    }
+    if let Some(connectivity) = self.connectivity {
+      connectivity.write_checks(&mut lines, " connectivity")?;
+    }
+    match self.dual_stack_listener {
+      Some(Ok(_)) if self.dual_stack_listener_works() => {
+        lines.line(format_args!("dual-stack listener: available"))?;
+      }
+      Some(Ok(peer)) => lines.line(format_args!(
+        "dual-stack listener: unavailable: IPv4 client seen as {}",
+        peer.ip()
+      ))?,
+      Some(Err(e)) => lines.line(format_args!("dual-stack listener: unavailable: {e}"))?,
+      None => {}
+    }
+    match self.v6only.map(|v6only| v6only.default_v6only()) {
+      Some(Ok(default)) => lines.line(format_args!("default IPV6_V6ONLY: {default}"))?,
+      Some(Err(e)) => lines.line(format_args!("default IPV6_V6ONLY: unknown: {e}"))?,
+      None => {}
+    }
+    if !self.overrides.is_empty() {
+      lines.line(format_args!("overrides: {}", self.overrides))?;
+    }
+    Ok(())
+  }
+}
+
+impl From<ProbeReport> for Probe {
+  #[inline]
+  fn from(report: ProbeReport) -> Self {
+    report.probe()
+  }
+}
+
+/// The results of the checks of a single transport protocol.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TransportReport {
+  #[cfg_attr(feature = "serde", serde(with = "crate::snapshot::check"))]
+  pub(crate) ipv4: Result<(), ProbeError>,
+  #[cfg_attr(feature = "serde", serde(with = "crate::snapshot::check"))]
+  pub(crate) ipv6: Result<(), ProbeError>,
+  #[cfg_attr(feature = "serde", serde(with = "crate::snapshot::check"))]
+  pub(crate) ipv4_mapped_ipv6: Result<(), ProbeError>,
+  #[cfg_attr(feature = "serde", serde(default, rename = "ipv6_v6only_error"))]
+  pub(crate) ipv6_v6only: Option<ProbeError>,
+  #[cfg_attr(
+    feature = "serde",
+    serde(default, rename = "ipv4_mapped_ipv6_v6only_error")
+  )]
+  pub(crate) ipv4_mapped_ipv6_v6only: Option<ProbeError>,
+}
+
+impl TransportReport {
+  /// Returns the result of the IPv4 check.
+  #[inline]
+  pub const fn ipv4(&self) -> Result<(), ProbeError> {
+    self.ipv4
+  }
+
+  /// Returns the result of the IPv6 check.
+  #[inline]
+  pub const fn ipv6(&self) -> Result<(), ProbeError> {
+    self.ipv6
+  }
+
+  /// Returns the result of the IPv4-mapped IPv6 check.
+  #[inline]
+  pub const fn ipv4_mapped_ipv6(&self) -> Result<(), ProbeError> {
+    self.ipv4_mapped_ipv6
+  }
+
+  /// Returns the error of setting `IPV6_V6ONLY` in the IPv6 check, if it
+  /// failed.
+  ///
+  /// The check still binds the socket, and succeeds if the bind does.
+  #[inline]
+  pub const fn ipv6_v6only_error(&self) -> Option<ProbeError> {
+    self.ipv6_v6only
+  }
+
+  /// Returns the error of clearing `IPV6_V6ONLY` in the IPv4-mapped IPv6
+  /// check, if it failed.
+  ///
+  /// The check still binds the socket, and succeeds if the bind does.
+  #[inline]
+  pub const fn ipv4_mapped_ipv6_v6only_error(&self) -> Option<ProbeError> {
+    self.ipv4_mapped_ipv6_v6only
+  }
+
+  /// Returns the capabilities summarized by this report.
+  #[inline]
+  pub const fn probe(&self) -> TransportProbe {
+    TransportProbe {
+      ipv4: self.ipv4.is_ok(),
+      ipv6: self.ipv6.is_ok(),
+      ipv4_mapped_ipv6: self.ipv4_mapped_ipv6.is_ok(),
+    }
+  }
+}
+
+impl TransportReport {
+  fn write_checks(&self, lines: &mut Lines<'_, '_>, transport: &str) -> fmt::Result {
+    lines.check(format_args!("{transport}IPv4"), self.ipv4)?;
+    lines.check(format_args!("{transport}IPv6"), self.ipv6)?;
+    if let Some(e) = self.ipv6_v6only {
+      lines.line(format_args!("{transport}IPv6: ignored: {e}"))?;
+    }
+    lines.check(
+      format_args!("{transport}IPv4-mapped IPv6"),
+      self.ipv4_mapped_ipv6,
+    )?;
+    if let Some(e) = self.ipv4_mapped_ipv6_v6only {
+      lines.line(format_args!("{transport}IPv4-mapped IPv6: ignored: {e}"))?;
+    }
+    Ok(())
+  }
+}
+
+impl fmt::Display for TransportReport {
+  /// Writes one line per check, e.g. `IPv4: available`.
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    self.write_checks(&mut Lines::new(f), "")
+  }
+}
+
+/// Writes lines separated by newlines, without a trailing one.
+pub(crate) struct Lines<'a, 'b> {
+  f: &'a mut fmt::Formatter<'b>,
+  first: bool,
+}
+
+impl<'a, 'b> Lines<'a, 'b> {
+  pub(crate) fn new(f: &'a mut fmt::Formatter<'b>) -> Self {
+    Self { f, first: true }
+  }
+
+  pub(crate) fn line(&mut self, line: fmt::Arguments<'_>) -> fmt::Result {
+    if !core::mem::take(&mut self.first) {
+      self.f.write_str("\n")?;
+    }
+    self.f.write_fmt(line)
+  }
+
+  /// Writes the result of a check, e.g. `IPv6: unavailable: bind ::1
+  /// failed (...)`.
+  pub(crate) fn check(
+    &mut self,
+    label: fmt::Arguments<'_>,
+    res: Result<(), ProbeError>,
+  ) -> fmt::Result {
+    match res {
+      Ok(()) => self.line(format_args!("{label}: available")),
+      Err(e) => self.line(format_args!("{label}: unavailable: {e}")),
+    }
+  }
+}
+
+#[test]
+fn test_reason() {
+  assert_eq!(Reason::from_errno(Errno::AFNOSUPPORT), Reason::Unsupported);
+  assert_eq!(Reason::from_errno(Errno::ACCESS), Reason::Denied);
+  assert_eq!(
+    Reason::from_errno(Errno::ADDRNOTAVAIL),
+    Reason::AddressNotAvailable
+  );
+  assert_eq!(Reason::from_errno(Errno::MFILE), Reason::ResourceExhausted);
+  assert_eq!(Reason::from_errno(Errno::INVAL), Reason::Other);
+}
+
+#[test]
+fn test_display() {
+  let err = ProbeError::new(
+    Step::Bind,
+    Errno::ADDRNOTAVAIL,
+    Some("[::1]:0".parse().unwrap()),
+  );
+  let ignored = ProbeError::new(Step::SetIpv6V6Only, Errno::INVAL, None);
+  let report = TransportReport {
+    ipv4: Ok(()),
+    ipv6: Err(err),
+    ipv4_mapped_ipv6: Ok(()),
+    ipv6_v6only: None,
+    ipv4_mapped_ipv6_v6only: Some(ignored),
+  };
+  assert_eq!(
+    report.to_string(),
+    format!(
+      "IPv4: available\nIPv6: unavailable: {err}\nIPv4-mapped IPv6: available\nIPv4-mapped IPv6: ignored: {ignored}"
+    )
+  );
+
+  let mut report = crate::ProbeBuilder::new().with_udp(false).report();
+  report.overrides = Overrides::NONE;
+  assert_eq!(report.to_string().lines().count(), 4);
+}
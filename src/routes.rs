@@ -0,0 +1,320 @@
+use std::{io, net::IpAddr};
+
+/// A default route of the host.
+///
+/// See [`default_route_v4`] and [`default_route_v6`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Route {
+  gateway: Option<IpAddr>,
+  interface: String,
+  metric: u32,
+}
+
+impl Route {
+  /// Returns the next hop, or `None` if the destinations are on-link,
+  /// e.g. for point-to-point links.
+  #[inline]
+  pub const fn gateway(&self) -> Option<IpAddr> {
+    self.gateway
+  }
+
+  /// Returns the name of the outgoing interface.
+  #[inline]
+  pub fn interface(&self) -> &str {
+    &self.interface
+  }
+
+  /// Returns the metric of the route, lower values are preferred.
+  #[inline]
+  pub const fn metric(&self) -> u32 {
+    self.metric
+  }
+}
+
+/// Returns the IPv4 default route with the lowest metric, or `None` if
+/// there is none.
+///
+/// The main routing table is read from rtnetlink on Linux, falling back to
+/// `/proc/net/route` where netlink sockets are forbidden. Other platforms
+/// fail with [`io::ErrorKind::Unsupported`].
+pub fn default_route_v4() -> io::Result<Option<Route>> {
+  imp::default_route(false)
+}
+
+/// Returns the IPv6 default route with the lowest metric, or `None` if
+/// there is none, see [`default_route_v4`].
+///
+/// The fallback reads `/proc/net/ipv6_route`.
+pub fn default_route_v6() -> io::Result<Option<Route>> {
+  imp::default_route(true)
+}
+
+#[cfg(target_os = "linux")]
+mod imp {
+  use std::{
+    fs,
+    net::{Ipv4Addr, Ipv6Addr},
+  };
+
+  use super::*;
+  use crate::{
+    interfaces::links,
+    netlink::{self, Attributes},
+  };
+
+  const ROUTE_PATH: &str = "/proc/net/route";
+  const IPV6_ROUTE_PATH: &str = "/proc/net/ipv6_route";
+
+  const AF_INET: u8 = 2;
+  const AF_INET6: u8 = 10;
+
+  /// `sizeof(struct rtmsg)`
+  const RTMSG_LEN: usize = 12;
+
+  const RT_TABLE_MAIN: u32 = 254;
+  const RTN_UNICAST: u8 = 1;
+
+  const RTA_OIF: u16 = 4;
+  const RTA_GATEWAY: u16 = 5;
+  const RTA_PRIORITY: u16 = 6;
+  const RTA_TABLE: u16 = 15;
+
+  const RTF_UP: u32 = 0x1;
+  const RTF_REJECT: u32 = 0x200;
+
+  /// A default route as found in a `RTM_NEWROUTE` message.
+  #[derive(Debug, PartialEq, Eq)]
+  pub(super) struct RawRoute {
+    pub(super) index: u32,
+    pub(super) gateway: Option<IpAddr>,
+    pub(super) metric: u32,
+  }
+
+  pub(super) fn default_route(ipv6: bool) -> io::Result<Option<Route>> {
+    let routes = match netlink_default_routes(ipv6) {
+      Ok(routes) => routes,
+      // netlink sockets may be forbidden in sandboxed environments
+      Err(_) => {
+        let path = if ipv6 { IPV6_ROUTE_PATH } else { ROUTE_PATH };
+        match fs::read_to_string(path) {
+          Ok(table) if ipv6 => parse_ipv6_route(&table),
+          Ok(table) => parse_route(&table),
+          // The file is missing if the family is disabled.
+          Err(e) if e.kind() == io::ErrorKind::NotFound => Vec::new(),
+          Err(e) => return Err(e),
+        }
+      }
+    };
+
+    // The first of equal metrics wins, like the kernel does.
+    Ok(routes.into_iter().min_by_key(|route| route.metric))
+  }
+
+  fn netlink_default_routes(ipv6: bool) -> io::Result<Vec<Route>> {
+    let mut req = [0; RTMSG_LEN];
+    req[0] = if ipv6 { AF_INET6 } else { AF_INET };
+
+    let mut raw = Vec::new();
+    netlink::dump(netlink::RTM_GETROUTE, &req, |hdr, payload| {
+      if hdr.ty == netlink::RTM_NEWROUTE {
+        raw.extend(parse_rtmsg(payload));
+      }
+    })?;
+    if raw.is_empty() {
+      return Ok(Vec::new());
+    }
+
+    let links = links()?;
+    Ok(
+      raw
+        .into_iter()
+        .filter_map(|route| {
+          let i = links
+            .binary_search_by_key(&route.index, |link| link.index())
+            .ok()?;
+          Some(Route {
+            gateway: route.gateway,
+            interface: links[i].name().to_owned(),
+            metric: route.metric,
+          })
+        })
+        .collect(),
+    )
+  }
+
+  /// Parses the payload of a `RTM_NEWROUTE` message, returns `None` unless
+  /// it is a unicast default route of the main table.
+  pub(super) fn parse_rtmsg(payload: &[u8]) -> Option<RawRoute> {
+    let (family, dst_len) = (*payload.first()?, *payload.get(1)?);
+    let (mut table, ty) = (u32::from(*payload.get(4)?), *payload.get(7)?);
+    if dst_len != 0 || ty != RTN_UNICAST {
+      return None;
+    }
+
+    let (mut index, mut gateway, mut metric) = (None, None, 0);
+    for (ty, data) in Attributes::new(payload.get(RTMSG_LEN..)?) {
+      let u32_data = <[u8; 4]>::try_from(data).ok();
+      match ty {
+        RTA_OIF => index = u32_data.map(u32::from_ne_bytes),
+        RTA_PRIORITY => metric = u32_data.map_or(0, u32::from_ne_bytes),
+        // Supersedes the 8 bit table of the header.
+        RTA_TABLE => table = u32_data.map_or(table, u32::from_ne_bytes),
+        RTA_GATEWAY => {
+          gateway = match (family, data.len()) {
+            (AF_INET, 4) => Some(IpAddr::V4(Ipv4Addr::from(u32_data?))),
+            (AF_INET6, 16) => Some(IpAddr::V6(Ipv6Addr::from(
+              <[u8; 16]>::try_from(data).unwrap(),
+            ))),
+            _ => None,
+          }
+        }
+        _ => {}
+      }
+    }
+
+    if table != RT_TABLE_MAIN {
+      return None;
+    }
+
+    // Multipath routes carry their next hops in `RTA_MULTIPATH` instead of
+    // `RTA_OIF`, and are skipped.
+    Some(RawRoute {
+      index: index?,
+      gateway,
+      metric,
+    })
+  }
+
+  /// Parses the default routes of `/proc/net/route`.
+  pub(super) fn parse_route(table: &str) -> Vec<Route> {
+    let hex = |s| u32::from_str_radix(s, 16).ok();
+
+    table
+      .lines()
+      // The header.
+      .skip(1)
+      .filter_map(|line| {
+        let fields: Vec<_> = line.split_whitespace().collect();
+        let [iface, dest, gateway, flags, _, _, metric, mask, ..] = fields[..] else {
+          return None;
+        };
+
+        let flags = hex(flags)?;
+        if hex(dest)? != 0 || hex(mask)? != 0 || flags & RTF_UP == 0 || flags & RTF_REJECT != 0 {
+          return None;
+        }
+
+        // The addresses are printed as native endian integers.
+        let gateway = Ipv4Addr::from(hex(gateway)?.to_ne_bytes());
+        Some(Route {
+          gateway: (!gateway.is_unspecified()).then_some(IpAddr::V4(gateway)),
+          interface: iface.to_owned(),
+          metric: metric.parse().ok()?,
+        })
+      })
+      .collect()
+  }
+
+  /// Parses the default routes of `/proc/net/ipv6_route`.
+  pub(super) fn parse_ipv6_route(table: &str) -> Vec<Route> {
+    let hex = |s| u32::from_str_radix(s, 16).ok();
+
+    table
+      .lines()
+      .filter_map(|line| {
+        let fields: Vec<_> = line.split_whitespace().collect();
+        let [dest, dest_len, _, _, gateway, metric, _, _, flags, iface] = fields[..] else {
+          return None;
+        };
+
+        let flags = hex(flags)?;
+        let dest = u128::from_str_radix(dest, 16).ok()?;
+        if dest != 0 || hex(dest_len)? != 0 || flags & RTF_UP == 0 || flags & RTF_REJECT != 0 {
+          return None;
+        }
+
+        let gateway = Ipv6Addr::from(u128::from_str_radix(gateway, 16).ok()?);
+        Some(Route {
+          gateway: (!gateway.is_unspecified()).then_some(IpAddr::V6(gateway)),
+          interface: iface.to_owned(),
+          metric: hex(metric)?,
+        })
+      })
+      .collect()
+  }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod imp {
+  use super::*;
+
+  pub(super) fn default_route(_ipv6: bool) -> io::Result<Option<Route>> {
+    Err(io::Error::new(
+      io::ErrorKind::Unsupported,
+      "route lookup is only supported on Linux",
+    ))
+  }
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn test_parse_proc() {
+  let ip = |s: &str| Some(s.parse::<IpAddr>().unwrap());
+
+  let routes = imp::parse_route(include_str!("../tests/fixtures/proc_net_route"));
+  assert_eq!(
+    routes
+      .iter()
+      .map(|route| (route.interface(), route.gateway(), route.metric()))
+      .collect::<Vec<_>>(),
+    [
+      ("wlan0", ip("192.168.1.1"), 600),
+      ("eth0", ip("192.0.2.1"), 100),
+    ]
+  );
+
+  // The unreachable default route of `lo` is skipped.
+  let routes = imp::parse_ipv6_route(include_str!("../tests/fixtures/proc_net_ipv6_route"));
+  assert_eq!(
+    routes
+      .iter()
+      .map(|route| (route.interface(), route.gateway(), route.metric()))
+      .collect::<Vec<_>>(),
+    [("wlan0", ip("fe80::1"), 1024), ("eth0", ip("fd00::1"), 256)]
+  );
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn test_parse_rtmsg() {
+  use crate::netlink::attribute;
+
+  let mut msg = vec![10, 0, 0, 0, 254, 3, 0, 1, 0, 0, 0, 0];
+  msg.extend(attribute(4, &2u32.to_ne_bytes()));
+  msg.extend(attribute(6, &1024u32.to_ne_bytes()));
+  msg.extend(attribute(
+    5,
+    &"fd00::1".parse::<std::net::Ipv6Addr>().unwrap().octets(),
+  ));
+  assert_eq!(
+    imp::parse_rtmsg(&msg),
+    Some(imp::RawRoute {
+      index: 2,
+      gateway: Some("fd00::1".parse().unwrap()),
+      metric: 1024,
+    })
+  );
+
+  // Not a default route.
+  msg[1] = 64;
+  assert_eq!(imp::parse_rtmsg(&msg), None);
+}
+
+#[test]
+fn test_default_route() {
+  if cfg!(target_os = "linux") {
+    default_route_v4().unwrap();
+    default_route_v6().unwrap();
+  }
+}
@@ -0,0 +1,344 @@
+//! Detection of sandboxes which restrict socket creation.
+
+use core::fmt;
+use std::{fs, path::Path};
+
+use rustix::net::{socket, AddressFamily, SocketType};
+
+use super::{ProbeError, Reason, Step};
+
+const PROC_NET: &str = "/proc/net";
+const PROC_SELF_STATUS: &str = "/proc/self/status";
+
+/// `SECCOMP_MODE_FILTER`
+const SECCOMP_MODE_FILTER: u32 = 2;
+
+/// The address families which are checked, the socket type used for each,
+/// the file of `/proc/net` which exists if the kernel supports the family,
+/// and whether creating a socket requires privileges.
+const FAMILIES: [(&str, AddressFamily, SocketType, Option<&str>, bool); 8] = [
+  (
+    "inet",
+    AddressFamily::INET,
+    SocketType::STREAM,
+    Some("tcp"),
+    false,
+  ),
+  (
+    "inet6",
+    AddressFamily::INET6,
+    SocketType::STREAM,
+    Some("if_inet6"),
+    false,
+  ),
+  (
+    "unix",
+    AddressFamily::UNIX,
+    SocketType::STREAM,
+    Some("unix"),
+    false,
+  ),
+  (
+    "netlink",
+    AddressFamily::NETLINK,
+    SocketType::DGRAM,
+    Some("netlink"),
+    false,
+  ),
+  (
+    "packet",
+    AddressFamily::PACKET,
+    SocketType::DGRAM,
+    Some("packet"),
+    true,
+  ),
+  (
+    "vsock",
+    AddressFamily::VSOCK,
+    SocketType::STREAM,
+    None,
+    false,
+  ),
+  (
+    "bluetooth",
+    AddressFamily::BLUETOOTH,
+    SocketType::SEQPACKET,
+    None,
+    false,
+  ),
+  ("xdp", AddressFamily::XDP, SocketType::RAW, None, true),
+];
+
+/// Whether sockets of an address family can be created.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+#[non_exhaustive]
+pub enum FamilySupport {
+  /// Sockets can be created.
+  Supported,
+  /// Socket creation is denied by a security policy such as seccomp, or by
+  /// missing privileges.
+  Denied,
+  /// The kernel does not support the family.
+  Unsupported,
+}
+
+impl FamilySupport {
+  /// Returns a short human readable description of the support.
+  #[inline]
+  pub const fn as_str(&self) -> &'static str {
+    match self {
+      Self::Supported => "supported",
+      Self::Denied => "denied",
+      Self::Unsupported => "unsupported",
+    }
+  }
+}
+
+impl fmt::Display for FamilySupport {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.write_str(self.as_str())
+  }
+}
+
+/// The result of creating a socket of a single address family.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FamilyReport {
+  #[cfg_attr(feature = "serde", serde(rename = "name", with = "family_name"))]
+  family: AddressFamily,
+  #[cfg_attr(feature = "serde", serde(with = "crate::snapshot::check"))]
+  result: Result<(), ProbeError>,
+  kernel_support: Option<bool>,
+  #[cfg_attr(feature = "serde", serde(rename = "requires_privilege"))]
+  privileged: bool,
+}
+
+impl FamilyReport {
+  /// Returns the name of the family, e.g. `inet6` or `netlink`.
+  #[inline]
+  pub fn name(&self) -> &'static str {
+    name_of(self.family).unwrap_or("unknown")
+  }
+
+  /// Returns the address family.
+  #[inline]
+  pub const fn family(&self) -> AddressFamily {
+    self.family
+  }
+
+  /// Returns the result of creating a socket.
+  #[inline]
+  pub const fn result(&self) -> Result<(), ProbeError> {
+    self.result
+  }
+
+  /// Returns whether `/proc/net` shows that the kernel supports the family,
+  /// or `None` if it does not tell.
+  #[inline]
+  pub const fn kernel_support(&self) -> Option<bool> {
+    self.kernel_support
+  }
+
+  /// Returns `true` if creating a socket requires `CAP_NET_RAW`, in which
+  /// case [`FamilySupport::Denied`] does not imply a sandbox.
+  #[inline]
+  pub const fn requires_privilege(&self) -> bool {
+    self.privileged
+  }
+
+  /// Classifies the result.
+  ///
+  /// `EAFNOSUPPORT` counts as [`FamilySupport::Denied`] if the kernel
+  /// supports the family, as seccomp filters such as systemd's
+  /// `RestrictAddressFamilies=` fake it.
+  pub fn support(&self) -> FamilySupport {
+    match self.result {
+      Ok(()) => FamilySupport::Supported,
+      Err(err) => match err.reason() {
+        Reason::Denied => FamilySupport::Denied,
+        Reason::Unsupported if self.kernel_support == Some(true) => FamilySupport::Denied,
+        _ => FamilySupport::Unsupported,
+      },
+    }
+  }
+}
+
+/// Which address families the process can create sockets of.
+///
+/// See [`sandbox`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SandboxReport {
+  families: Vec<FamilyReport>,
+  seccomp: Option<bool>,
+}
+
+impl SandboxReport {
+  /// Returns the results of every checked family.
+  #[inline]
+  pub fn families(&self) -> &[FamilyReport] {
+    &self.families
+  }
+
+  /// Returns the result of `family`, if it was checked.
+  pub fn family(&self, family: AddressFamily) -> Option<&FamilyReport> {
+    self.families.iter().find(|report| report.family == family)
+  }
+
+  /// Returns `true` if a seccomp filter is installed on the process, or
+  /// `None` if `/proc/self/status` cannot be read.
+  #[inline]
+  pub const fn seccomp(&self) -> Option<bool> {
+    self.seccomp
+  }
+
+  /// Returns `true` if the process appears to be restricted to a subset of
+  /// the address families, like systemd's `RestrictAddressFamilies=` does.
+  ///
+  /// This is the case if a family which needs no privileges is denied.
+  pub fn restricted(&self) -> bool {
+    self
+      .families
+      .iter()
+      .any(|report| !report.privileged && report.support() == FamilySupport::Denied)
+  }
+}
+
+/// Creates a socket of a wide set of address families, e.g. `AF_INET6`,
+/// `AF_UNIX`, `AF_NETLINK` and `AF_VSOCK`, and classifies whether each is
+/// supported, denied by a sandbox or unsupported by the kernel.
+///
+/// Sandboxes such as gVisor, the default seccomp profile of Docker, Flatpak
+/// or systemd's `RestrictAddressFamilies=` make socket creation fail with
+/// `EPERM` or `EAFNOSUPPORT`, the latter looking like a kernel without
+/// support. `/proc/net` and `/proc/self/status` are consulted to tell them
+/// apart. The result is not cached.
+pub fn sandbox() -> SandboxReport {
+  let proc_net = Path::new(PROC_NET);
+  let families = FAMILIES
+    .iter()
+    .map(|&(_, family, ty, proc_file, privileged)| FamilyReport {
+      family,
+      result: socket(family, ty, None)
+        .map(drop)
+        .map_err(|e| ProbeError::new(Step::Socket, e, None)),
+      kernel_support: proc_file
+        .filter(|_| proc_net.is_dir())
+        .map(|file| proc_net.join(file).exists()),
+      privileged,
+    })
+    .collect();
+
+  SandboxReport {
+    families,
+    seccomp: fs::read_to_string(PROC_SELF_STATUS)
+      .ok()
+      .and_then(|status| parse_seccomp(&status)),
+  }
+}
+
+/// Returns the name of a checked family.
+fn name_of(family: AddressFamily) -> Option<&'static str> {
+  FAMILIES
+    .iter()
+    .find(|entry| entry.1 == family)
+    .map(|entry| entry.0)
+}
+
+/// Serializes an [`AddressFamily`] as its name, e.g. `inet6`.
+#[cfg(feature = "serde")]
+mod family_name {
+  use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+  use super::*;
+
+  pub(super) fn serialize<S: Serializer>(
+    family: &AddressFamily,
+    serializer: S,
+  ) -> Result<S::Ok, S::Error> {
+    match name_of(*family) {
+      Some(name) => serializer.serialize_str(name),
+      None => Err(serde::ser::Error::custom("unknown address family")),
+    }
+  }
+
+  pub(super) fn deserialize<'de, D: Deserializer<'de>>(
+    deserializer: D,
+  ) -> Result<AddressFamily, D::Error> {
+    let name = String::deserialize(deserializer)?;
+    FAMILIES
+      .iter()
+      .find(|entry| entry.0 == name)
+      .map(|entry| entry.1)
+      .ok_or_else(|| D::Error::custom(format_args!("unknown address family `{name}`")))
+  }
+}
+
+/// Parses the `Seccomp:` line of `/proc/self/status`.
+fn parse_seccomp(status: &str) -> Option<bool> {
+  let mode = status
+    .lines()
+    .find_map(|line| line.strip_prefix("Seccomp:"))?;
+  Some(mode.trim().parse::<u32>().ok()? == SECCOMP_MODE_FILTER)
+}
+
+#[test]
+fn test_support() {
+  use rustix::io::Errno;
+
+  let report = |errno: Option<Errno>, kernel_support| FamilyReport {
+    family: AddressFamily::INET6,
+    result: errno.map_or(Ok(()), |e| Err(ProbeError::new(Step::Socket, e, None))),
+    kernel_support,
+    privileged: false,
+  };
+  assert_eq!(report(None, None).support(), FamilySupport::Supported);
+  assert_eq!(
+    report(Some(Errno::PERM), None).support(),
+    FamilySupport::Denied
+  );
+  assert_eq!(
+    report(Some(Errno::AFNOSUPPORT), Some(false)).support(),
+    FamilySupport::Unsupported
+  );
+  // The kernel supports the family, a seccomp filter fakes the error.
+  assert_eq!(
+    report(Some(Errno::AFNOSUPPORT), Some(true)).support(),
+    FamilySupport::Denied
+  );
+
+  let sandbox = SandboxReport {
+    families: vec![
+      report(None, Some(true)),
+      report(Some(Errno::AFNOSUPPORT), Some(true)),
+    ],
+    seccomp: Some(true),
+  };
+  assert!(sandbox.restricted());
+
+  assert_eq!(parse_seccomp("Name:\tcat\nSeccomp:\t2\n"), Some(true));
+  assert_eq!(
+    parse_seccomp("Seccomp:\t0\nSeccomp_filters:\t0\n"),
+    Some(false)
+  );
+  assert_eq!(parse_seccomp("Name:\tcat\n"), None);
+}
+
+#[test]
+fn test_sandbox() {
+  let report = sandbox();
+  assert_eq!(report.families().len(), FAMILIES.len());
+  assert_eq!(
+    report.family(AddressFamily::UNIX).unwrap().support(),
+    FamilySupport::Supported
+  );
+  if crate::probe_fresh().ipv6() {
+    assert!(report
+      .family(AddressFamily::INET6)
+      .unwrap()
+      .result()
+      .is_ok());
+  }
+}
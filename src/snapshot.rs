@@ -0,0 +1,576 @@
+use std::net::SocketAddr;
+
+use rustix::io::Errno;
+use serde::{de::Error, ser::SerializeStruct, Deserialize, Deserializer, Serialize, Serializer};
+
+use super::{
+  Capabilities, CapabilityChange, Overrides, Probe, ProbeDiff, ProbeError, ProbeReport, Step,
+};
+
+/// A versioned record of the capabilities of the host, to store probe
+/// results or ship them to another machine.
+///
+/// The serialized form is described at [`Snapshot::VERSION`].
+///
+/// ```rust
+/// use iprobe::{try_probe, Snapshot};
+///
+/// let snapshot = Snapshot::new(try_probe());
+/// let json = serde_json::to_string(&snapshot).unwrap();
+/// assert_eq!(serde_json::from_str::<Snapshot>(&json).unwrap(), snapshot);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Snapshot {
+  #[serde(deserialize_with = "version")]
+  version: u32,
+  probe: Probe,
+  #[serde(default)]
+  overrides: Overrides,
+  report: Option<ProbeReport>,
+}
+
+impl Snapshot {
+  /// The version of the snapshots written by this release.
+  ///
+  /// The version is bumped whenever a field is removed or changes its
+  /// meaning, new fields are only added as optional ones, so that stored
+  /// snapshots stay readable. Snapshots of newer versions are rejected.
+  ///
+  /// The serialized types of version 1 follow these rules:
+  ///
+  /// - Structs are objects whose fields are named after their getters,
+  ///   e.g. `ipv4_mapped_ipv6`. Missing optional values are `null`.
+  /// - Enums such as [`Step`] and [`Reason`](crate::Reason) are `snake_case`
+  ///   strings, e.g. `address_not_available`.
+  /// - Results are `{"ok": true}`, with a `"value"` if the check produces
+  ///   one, or `{"ok": false, "error": ...}`.
+  /// - Errors are `{"step": ..., "reason": ..., "errno": ..., "errno_name":
+  ///   ..., "address": ...}`. `errno` is the raw code of the platform, and
+  ///   `errno_name` its portable name, e.g. `addrnotavail` for
+  ///   `EADDRNOTAVAIL`, which is preferred when reading a snapshot.
+  /// - [`Capabilities`] are lists of names, e.g. `["ipv4", "udp_ipv4"]`,
+  ///   named after the accessors of [`Probe`], with a `udp_` prefix for UDP.
+  /// - Addresses are strings, e.g. `"[::1]:0"`.
+  /// - The interfaces of the IPv6 sysctls are
+  ///   `{"name": "eth0", "disable_ipv6": false}` objects.
+  /// - A [`ProbeDiff`] is the list of its changes, e.g.
+  ///   `[{"capability": "ipv6", "available": false, "reason": ...}]`, with
+  ///   a single capability name and a `null` reason if unknown.
+  /// - The `probe` is the raw result of the checks, the `overrides` which
+  ///   were in effect are recorded next to it, see [`Overrides`].
+  pub const VERSION: u32 = 1;
+
+  /// Creates a snapshot of a report and the capabilities it summarizes.
+  #[inline]
+  pub fn new(report: ProbeReport) -> Self {
+    Self {
+      version: Self::VERSION,
+      probe: report.probe(),
+      overrides: report.overrides(),
+      report: Some(report),
+    }
+  }
+
+  /// Creates a snapshot of the capabilities alone.
+  #[inline]
+  pub const fn from_probe(probe: Probe) -> Self {
+    Self {
+      version: Self::VERSION,
+      probe,
+      overrides: Overrides::NONE,
+      report: None,
+    }
+  }
+
+  /// Returns the version the snapshot was written with.
+  #[inline]
+  pub const fn version(&self) -> u32 {
+    self.version
+  }
+
+  /// Returns the capabilities.
+  #[inline]
+  pub const fn probe(&self) -> Probe {
+    self.probe
+  }
+
+  /// Returns the overrides which were in effect when the report was made,
+  /// see [`ProbeReport::overrides`].
+  #[inline]
+  pub const fn overrides(&self) -> Overrides {
+    self.overrides
+  }
+
+  /// Returns the report the capabilities were summarized from, if any.
+  #[inline]
+  pub const fn report(&self) -> Option<&ProbeReport> {
+    self.report.as_ref()
+  }
+
+  /// Compares this snapshot with a newer one, see [`diff`](crate::diff).
+  ///
+  /// The changes have reasons only if both snapshots contain a report.
+  pub fn diff(&self, newer: &Self) -> ProbeDiff {
+    match (&self.report, &newer.report) {
+      (Some(old), Some(new)) => crate::diff(old, new),
+      _ => ProbeDiff::between(self.probe, newer.probe),
+    }
+  }
+}
+
+impl From<Probe> for Snapshot {
+  #[inline]
+  fn from(probe: Probe) -> Self {
+    Self::from_probe(probe)
+  }
+}
+
+impl From<ProbeReport> for Snapshot {
+  #[inline]
+  fn from(report: ProbeReport) -> Self {
+    Self::new(report)
+  }
+}
+
+fn version<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u32, D::Error> {
+  let version = u32::deserialize(deserializer)?;
+  if version > Snapshot::VERSION {
+    return Err(D::Error::custom(format_args!(
+      "snapshot version {version} is newer than the supported version {}",
+      Snapshot::VERSION
+    )));
+  }
+  Ok(version)
+}
+
+impl Serialize for ProbeError {
+  fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+    let mut state = serializer.serialize_struct("ProbeError", 5)?;
+    state.serialize_field("step", &self.step())?;
+    state.serialize_field("reason", &self.reason())?;
+    state.serialize_field("errno", &self.errno().raw_os_error())?;
+    state.serialize_field("errno_name", &errno_name(self.errno()))?;
+    state.serialize_field("address", &self.address())?;
+    state.end()
+  }
+}
+
+impl<'de> Deserialize<'de> for ProbeError {
+  fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+    // The reason is derived from the error code.
+    #[derive(Deserialize)]
+    struct Repr {
+      step: Step,
+      errno: i32,
+      #[serde(default)]
+      errno_name: Option<String>,
+      #[serde(default)]
+      address: Option<SocketAddr>,
+    }
+
+    let repr = Repr::deserialize(deserializer)?;
+    let named = repr
+      .errno_name
+      .and_then(|name| ERRNO_NAMES.iter().find(|(_, n)| *n == name));
+    let errno = match named {
+      Some((errno, _)) => *errno,
+      // Error codes are below 4096 on every supported platform.
+      None if (1..4096).contains(&repr.errno) => Errno::from_raw_os_error(repr.errno),
+      None => {
+        return Err(D::Error::custom(format_args!(
+          "invalid error code {}",
+          repr.errno
+        )))
+      }
+    };
+    Ok(Self::new(repr.step, errno, repr.address))
+  }
+}
+
+/// The serialized form of a result.
+#[derive(Serialize, Deserialize)]
+struct ResultRepr<T> {
+  ok: bool,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  value: Option<T>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  error: Option<ProbeError>,
+}
+
+/// Serializes the result of a check without a value as `{"ok": true}` or
+/// `{"ok": false, "error": ...}`.
+pub(crate) mod check {
+  use super::*;
+
+  pub(crate) fn serialize<S: Serializer>(
+    res: &Result<(), ProbeError>,
+    serializer: S,
+  ) -> Result<S::Ok, S::Error> {
+    ResultRepr::<()> {
+      ok: res.is_ok(),
+      value: None,
+      error: res.err(),
+    }
+    .serialize(serializer)
+  }
+
+  pub(crate) fn deserialize<'de, D: Deserializer<'de>>(
+    deserializer: D,
+  ) -> Result<Result<(), ProbeError>, D::Error> {
+    let repr = ResultRepr::<()>::deserialize(deserializer)?;
+    match (repr.ok, repr.error) {
+      (true, _) => Ok(Ok(())),
+      (false, Some(error)) => Ok(Err(error)),
+      (false, None) => Err(D::Error::missing_field("error")),
+    }
+  }
+}
+
+/// Serializes the result of a check as `{"ok": true, "value": ...}` or
+/// `{"ok": false, "error": ...}`.
+pub(crate) mod result {
+  use super::*;
+
+  pub(crate) fn serialize<S: Serializer, T: Serialize + Copy>(
+    res: &Result<T, ProbeError>,
+    serializer: S,
+  ) -> Result<S::Ok, S::Error> {
+    repr(*res).serialize(serializer)
+  }
+
+  pub(crate) fn deserialize<'de, D: Deserializer<'de>, T: Deserialize<'de>>(
+    deserializer: D,
+  ) -> Result<Result<T, ProbeError>, D::Error> {
+    from_repr(ResultRepr::deserialize(deserializer)?)
+  }
+
+  fn repr<T>(res: Result<T, ProbeError>) -> ResultRepr<T> {
+    match res {
+      Ok(value) => ResultRepr {
+        ok: true,
+        value: Some(value),
+        error: None,
+      },
+      Err(error) => ResultRepr {
+        ok: false,
+        value: None,
+        error: Some(error),
+      },
+    }
+  }
+
+  fn from_repr<T, E: Error>(repr: ResultRepr<T>) -> Result<Result<T, ProbeError>, E> {
+    match (repr.ok, repr.value, repr.error) {
+      (true, Some(value), _) => Ok(Ok(value)),
+      (true, None, _) => Err(E::missing_field("value")),
+      (false, _, Some(error)) => Ok(Err(error)),
+      (false, _, None) => Err(E::missing_field("error")),
+    }
+  }
+
+  /// Serializes an optional result, `null` if the check was not run.
+  pub(crate) mod option {
+    use super::*;
+
+    pub(crate) fn serialize<S: Serializer, T: Serialize + Copy>(
+      res: &Option<Result<T, ProbeError>>,
+      serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+      res.map(repr).serialize(serializer)
+    }
+
+    pub(crate) fn deserialize<'de, D: Deserializer<'de>, T: Deserialize<'de>>(
+      deserializer: D,
+    ) -> Result<Option<Result<T, ProbeError>>, D::Error> {
+      Option::<ResultRepr<T>>::deserialize(deserializer)?
+        .map(from_repr)
+        .transpose()
+    }
+  }
+}
+
+/// Serializes the interfaces of the IPv6 sysctls as
+/// `{"name": ..., "disable_ipv6": ...}` objects.
+#[cfg(target_os = "linux")]
+pub(crate) mod interfaces {
+  use super::*;
+
+  #[derive(Serialize, Deserialize)]
+  struct Interface<N> {
+    name: N,
+    disable_ipv6: bool,
+  }
+
+  pub(crate) fn serialize<S: Serializer>(
+    interfaces: &[(String, bool)],
+    serializer: S,
+  ) -> Result<S::Ok, S::Error> {
+    serializer.collect_seq(interfaces.iter().map(|(name, disable_ipv6)| Interface {
+      name: name.as_str(),
+      disable_ipv6: *disable_ipv6,
+    }))
+  }
+
+  pub(crate) fn deserialize<'de, D: Deserializer<'de>>(
+    deserializer: D,
+  ) -> Result<Vec<(String, bool)>, D::Error> {
+    let interfaces = Vec::<Interface<String>>::deserialize(deserializer)?;
+    Ok(
+      interfaces
+        .into_iter()
+        .map(|iface| (iface.name, iface.disable_ipv6))
+        .collect(),
+    )
+  }
+}
+
+/// The serialized names of the single capabilities.
+const CAPABILITY_NAMES: [(Capabilities, &str); 6] = [
+  (Capabilities::IPV4, "ipv4"),
+  (Capabilities::IPV6, "ipv6"),
+  (Capabilities::IPV4_MAPPED, "ipv4_mapped_ipv6"),
+  (Capabilities::UDP_IPV4, "udp_ipv4"),
+  (Capabilities::UDP_IPV6, "udp_ipv6"),
+  (Capabilities::UDP_IPV4_MAPPED, "udp_ipv4_mapped_ipv6"),
+];
+
+impl Serialize for Capabilities {
+  fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.collect_seq(
+      CAPABILITY_NAMES
+        .iter()
+        .filter(|(capability, _)| self.contains(*capability))
+        .map(|(_, name)| name),
+    )
+  }
+}
+
+impl<'de> Deserialize<'de> for Capabilities {
+  fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+    let mut capabilities = Self::empty();
+    for name in Vec::<String>::deserialize(deserializer)? {
+      capabilities |= capability_named(&name)?;
+    }
+    Ok(capabilities)
+  }
+}
+
+/// Returns the single capability called `name`.
+fn capability_named<E: Error>(name: &str) -> Result<Capabilities, E> {
+  const NAMES: &[&str] = &[
+    "ipv4",
+    "ipv6",
+    "ipv4_mapped_ipv6",
+    "udp_ipv4",
+    "udp_ipv6",
+    "udp_ipv4_mapped_ipv6",
+  ];
+
+  CAPABILITY_NAMES
+    .iter()
+    .find(|(_, n)| *n == name)
+    .map(|(capability, _)| *capability)
+    .ok_or_else(|| E::unknown_variant(name, NAMES))
+}
+
+/// Serializes a single capability as its name, e.g. `"ipv6"`.
+pub(crate) mod capability {
+  use super::*;
+
+  pub(crate) fn serialize<S: Serializer>(
+    capability: &Capabilities,
+    serializer: S,
+  ) -> Result<S::Ok, S::Error> {
+    let (_, name) = CAPABILITY_NAMES
+      .iter()
+      .find(|(single, _)| single == capability)
+      .ok_or_else(|| serde::ser::Error::custom("not a single capability"))?;
+    serializer.serialize_str(name)
+  }
+
+  pub(crate) fn deserialize<'de, D: Deserializer<'de>>(
+    deserializer: D,
+  ) -> Result<Capabilities, D::Error> {
+    capability_named(&String::deserialize(deserializer)?)
+  }
+}
+
+impl Serialize for ProbeDiff {
+  fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.collect_seq(self.changes())
+  }
+}
+
+impl<'de> Deserialize<'de> for ProbeDiff {
+  fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+    let mut diff = Self {
+      gained: Capabilities::empty(),
+      lost: Capabilities::empty(),
+      reasons: [None; 6],
+    };
+    for change in Vec::<CapabilityChange>::deserialize(deserializer)? {
+      let capability = change.capability();
+      if diff.gained.union(diff.lost).contains(capability) {
+        return Err(D::Error::custom(format_args!(
+          "duplicate change of {}",
+          capability.label()
+        )));
+      }
+      if change.available() {
+        diff.gained |= capability;
+      } else {
+        diff.lost |= capability;
+      }
+      if let Some(index) = Capabilities::SINGLE.iter().position(|c| *c == capability) {
+        diff.reasons[index] = change.reason();
+      }
+    }
+    Ok(diff)
+  }
+}
+
+/// The error codes the checks commonly fail with, named after their POSIX
+/// names without the `E` prefix.
+const ERRNO_NAMES: &[(Errno, &str)] = &[
+  (Errno::ACCESS, "acces"),
+  (Errno::ADDRINUSE, "addrinuse"),
+  (Errno::ADDRNOTAVAIL, "addrnotavail"),
+  (Errno::AFNOSUPPORT, "afnosupport"),
+  (Errno::AGAIN, "again"),
+  (Errno::CONNABORTED, "connaborted"),
+  (Errno::CONNREFUSED, "connrefused"),
+  (Errno::CONNRESET, "connreset"),
+  (Errno::HOSTUNREACH, "hostunreach"),
+  (Errno::INPROGRESS, "inprogress"),
+  (Errno::INTR, "intr"),
+  (Errno::INVAL, "inval"),
+  (Errno::MFILE, "mfile"),
+  (Errno::NETDOWN, "netdown"),
+  (Errno::NETUNREACH, "netunreach"),
+  (Errno::NOBUFS, "nobufs"),
+  (Errno::NOPROTOOPT, "noprotoopt"),
+  (Errno::OPNOTSUPP, "opnotsupp"),
+  (Errno::PFNOSUPPORT, "pfnosupport"),
+  (Errno::PROTONOSUPPORT, "protonosupport"),
+  (Errno::PROTOTYPE, "prototype"),
+  (Errno::TIMEDOUT, "timedout"),
+  #[cfg(not(windows))]
+  (Errno::NFILE, "nfile"),
+  #[cfg(not(windows))]
+  (Errno::NOMEM, "nomem"),
+  #[cfg(not(windows))]
+  (Errno::PERM, "perm"),
+];
+
+/// Returns the portable name of an error code, if it has one.
+fn errno_name(errno: Errno) -> Option<&'static str> {
+  ERRNO_NAMES
+    .iter()
+    .find(|(e, _)| *e == errno)
+    .map(|(_, name)| *name)
+}
+
+#[test]
+fn test_snapshot() {
+  use core::time::Duration;
+
+  let snapshot = Snapshot::new(crate::try_probe_deep(Duration::from_secs(1)));
+  let json = serde_json::to_string(&snapshot).unwrap();
+  assert_eq!(serde_json::from_str::<Snapshot>(&json).unwrap(), snapshot);
+  let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+  assert_eq!(value["overrides"], value["report"]["overrides"]);
+  let listener = &value["report"]["dual_stack_listener"];
+  assert!(listener["value"].is_string() || listener["error"].is_object());
+  #[cfg(target_os = "linux")]
+  if let Some(interfaces) = value["report"]["ipv6_sysctls"]["interfaces"].as_array() {
+    assert!(interfaces
+      .iter()
+      .all(|iface| iface["name"].is_string() && iface["disable_ipv6"].is_boolean()));
+  }
+
+  let err = ProbeError::new(
+    Step::Bind,
+    Errno::ADDRNOTAVAIL,
+    Some("[::1]:0".parse::<SocketAddr>().unwrap()),
+  );
+  let json = serde_json::to_string(&err).unwrap();
+  assert_eq!(
+    json,
+    format!(
+      r#"{{"step":"bind","reason":"address_not_available","errno":{},"errno_name":"addrnotavail","address":"[::1]:0"}}"#,
+      Errno::ADDRNOTAVAIL.raw_os_error()
+    )
+  );
+  // The name wins over a raw code written on another platform.
+  let foreign = json.replace(
+    &format!(r#""errno":{}"#, Errno::ADDRNOTAVAIL.raw_os_error()),
+    r#""errno":49"#,
+  );
+  assert_eq!(serde_json::from_str::<ProbeError>(&foreign).unwrap(), err);
+  let unnamed = r#"{"step":"bind","errno":200,"address":null}"#;
+  assert_eq!(
+    serde_json::from_str::<ProbeError>(unnamed).unwrap().errno(),
+    Errno::from_raw_os_error(200)
+  );
+  let invalid = r#"{"step":"bind","errno":99999,"address":null}"#;
+  assert!(serde_json::from_str::<ProbeError>(invalid).is_err());
+
+  let report = crate::TransportReport {
+    ipv4: Ok(()),
+    ipv6: Err(err),
+    ipv4_mapped_ipv6: Ok(()),
+    ipv6_v6only: None,
+    ipv4_mapped_ipv6_v6only: None,
+  };
+  let json = serde_json::to_value(report).unwrap();
+  assert_eq!(json["ipv4"], serde_json::json!({ "ok": true }));
+  assert_eq!(
+    json["ipv6"],
+    serde_json::json!({ "ok": false, "error": serde_json::to_value(err).unwrap() })
+  );
+  assert_eq!(
+    serde_json::from_value::<crate::TransportReport>(json).unwrap(),
+    report
+  );
+
+  let capabilities = Capabilities::IPV4 | Capabilities::UDP_IPV4_MAPPED;
+  let json = serde_json::to_string(&capabilities).unwrap();
+  assert_eq!(json, r#"["ipv4","udp_ipv4_mapped_ipv6"]"#);
+  assert_eq!(
+    serde_json::from_str::<Capabilities>(&json).unwrap(),
+    capabilities
+  );
+  assert!(serde_json::from_str::<Capabilities>(r#"["ipv5"]"#).is_err());
+
+  let json = serde_json::to_string(&Snapshot::from_probe(crate::probe())).unwrap();
+  let newer = json.replace(r#""version":1"#, r#""version":2"#);
+  assert!(serde_json::from_str::<Snapshot>(&newer).is_err());
+
+  assert!(snapshot.diff(&snapshot).is_empty());
+  let none = Snapshot::from_probe(Probe::from_capabilities(crate::Capabilities::empty()));
+  assert_eq!(
+    none.diff(&snapshot).gained(),
+    snapshot.probe().capabilities()
+  );
+
+  let old = Probe::from_capabilities(Capabilities::IPV4 | Capabilities::IPV6);
+  let new = Probe::from_capabilities(Capabilities::IPV4 | Capabilities::UDP_IPV4);
+  let mut diff = ProbeDiff::between(old, new);
+  diff.reasons[1] = Some(err);
+  let json = serde_json::to_value(diff).unwrap();
+  assert_eq!(
+    json,
+    serde_json::json!([
+      { "capability": "ipv6", "available": false, "reason": serde_json::to_value(err).unwrap() },
+      { "capability": "udp_ipv4", "available": true, "reason": null },
+    ])
+  );
+  assert_eq!(serde_json::from_value::<ProbeDiff>(json).unwrap(), diff);
+  let duplicate = serde_json::json!([
+    { "capability": "ipv6", "available": false, "reason": null },
+    { "capability": "ipv6", "available": true, "reason": null },
+  ]);
+  assert!(serde_json::from_value::<ProbeDiff>(duplicate).is_err());
+  let flags = serde_json::json!([{ "capability": "IPV6", "available": true, "reason": null }]);
+  assert!(serde_json::from_value::<ProbeDiff>(flags).is_err());
+}
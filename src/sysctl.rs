@@ -0,0 +1,162 @@
+//! The IPv6 related kernel parameters of Linux.
+
+use std::{fs, path::Path};
+
+const PROC_SYS_NET: &str = "/proc/sys/net";
+
+/// The IPv6 related sysctls of the Linux kernel, which explain why IPv6
+/// checks fail.
+///
+/// See [`ProbeReport::ipv6_sysctls`](crate::ProbeReport::ipv6_sysctls).
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Ipv6Sysctls {
+  kernel_ipv6: bool,
+  all_disable_ipv6: Option<bool>,
+  default_disable_ipv6: Option<bool>,
+  bindv6only: Option<bool>,
+  #[cfg_attr(feature = "serde", serde(with = "crate::snapshot::interfaces"))]
+  interfaces: Vec<(String, bool)>,
+}
+
+impl Ipv6Sysctls {
+  /// Reads the sysctls from `/proc/sys/net`, returns `None` if it cannot be
+  /// read, e.g. because `/proc` is not mounted.
+  pub fn read() -> Option<Self> {
+    read_from(Path::new(PROC_SYS_NET))
+  }
+
+  /// Returns `false` if `/proc/sys/net/ipv6` does not exist, i.e. IPv6 is
+  /// disabled on the kernel command line with `ipv6.disable=1`, or the
+  /// kernel is built without IPv6.
+  #[inline]
+  pub const fn kernel_ipv6(&self) -> bool {
+    self.kernel_ipv6
+  }
+
+  /// Returns `net.ipv6.conf.all.disable_ipv6`, which disables IPv6 on all
+  /// interfaces when set.
+  #[inline]
+  pub const fn all_disable_ipv6(&self) -> Option<bool> {
+    self.all_disable_ipv6
+  }
+
+  /// Returns `net.ipv6.conf.default.disable_ipv6`, which applies to
+  /// interfaces created later.
+  #[inline]
+  pub const fn default_disable_ipv6(&self) -> Option<bool> {
+    self.default_disable_ipv6
+  }
+
+  /// Returns `net.ipv6.bindv6only`, the default of `IPV6_V6ONLY` for new
+  /// sockets.
+  #[inline]
+  pub const fn bindv6only(&self) -> Option<bool> {
+    self.bindv6only
+  }
+
+  /// Returns `net.ipv6.conf.<interface>.disable_ipv6` of every interface,
+  /// ordered by name.
+  #[inline]
+  pub fn interfaces(&self) -> &[(String, bool)] {
+    &self.interfaces
+  }
+
+  /// Returns `net.ipv6.conf.<name>.disable_ipv6`, or `None` if there is no
+  /// such interface.
+  pub fn interface_disable_ipv6(&self, name: &str) -> Option<bool> {
+    self
+      .interfaces
+      .iter()
+      .find(|(iface, _)| iface == name)
+      .map(|(_, disabled)| *disabled)
+  }
+
+  /// Returns `true` if IPv6 is disabled by the kernel command line or by
+  /// `net.ipv6.conf.all.disable_ipv6`.
+  #[inline]
+  pub fn ipv6_disabled(&self) -> bool {
+    !self.kernel_ipv6 || self.all_disable_ipv6 == Some(true)
+  }
+}
+
+/// Reads `net.ipv6.bindv6only` alone.
+pub(crate) fn bindv6only() -> Option<bool> {
+  flag(&Path::new(PROC_SYS_NET).join("ipv6/bindv6only"))
+}
+
+/// Reads a boolean sysctl.
+fn flag(path: &Path) -> Option<bool> {
+  let value = fs::read_to_string(path).ok()?;
+  value.trim().parse::<u32>().ok().map(|value| value != 0)
+}
+
+/// Reads the sysctls from `net`, which mirrors `/proc/sys/net`.
+fn read_from(net: &Path) -> Option<Ipv6Sysctls> {
+  if !net.is_dir() {
+    return None;
+  }
+
+  let ipv6 = net.join("ipv6");
+
+  let conf = ipv6.join("conf");
+  let mut interfaces: Vec<_> = fs::read_dir(&conf)
+    .into_iter()
+    .flatten()
+    .filter_map(|entry| {
+      let name = entry.ok()?.file_name().into_string().ok()?;
+      if name == "all" || name == "default" {
+        return None;
+      }
+
+      let disabled = flag(&conf.join(&name).join("disable_ipv6"))?;
+      Some((name, disabled))
+    })
+    .collect();
+  interfaces.sort();
+
+  Some(Ipv6Sysctls {
+    kernel_ipv6: ipv6.is_dir(),
+    all_disable_ipv6: flag(&conf.join("all/disable_ipv6")),
+    default_disable_ipv6: flag(&conf.join("default/disable_ipv6")),
+    bindv6only: flag(&ipv6.join("bindv6only")),
+    interfaces,
+  })
+}
+
+#[test]
+fn test_read_from() {
+  let root = std::env::temp_dir().join(format!("iprobe-sysctl-{}", std::process::id()));
+  let net = root.join("net");
+  let write = |path: &str, value: &str| {
+    let path = net.join(path);
+    fs::create_dir_all(path.parent().unwrap()).unwrap();
+    fs::write(path, value).unwrap();
+  };
+
+  assert_eq!(read_from(&net), None);
+
+  fs::create_dir_all(net.join("ipv4")).unwrap();
+  let sysctls = read_from(&net).unwrap();
+  assert!(!sysctls.kernel_ipv6() && sysctls.ipv6_disabled());
+  assert_eq!(sysctls.all_disable_ipv6(), None);
+
+  write("ipv6/bindv6only", "0\n");
+  write("ipv6/conf/all/disable_ipv6", "0\n");
+  write("ipv6/conf/default/disable_ipv6", "1\n");
+  write("ipv6/conf/lo/disable_ipv6", "0\n");
+  write("ipv6/conf/eth0/disable_ipv6", "1\n");
+  let sysctls = read_from(&net).unwrap();
+  assert!(sysctls.kernel_ipv6() && !sysctls.ipv6_disabled());
+  assert_eq!(sysctls.all_disable_ipv6(), Some(false));
+  assert_eq!(sysctls.default_disable_ipv6(), Some(true));
+  assert_eq!(sysctls.bindv6only(), Some(false));
+  assert_eq!(
+    sysctls.interfaces(),
+    [("eth0".to_string(), true), ("lo".to_string(), false)]
+  );
+  assert_eq!(sysctls.interface_disable_ipv6("lo"), Some(false));
+  assert_eq!(sysctls.interface_disable_ipv6("wlan0"), None);
+
+  fs::remove_dir_all(root).unwrap();
+}
@@ -0,0 +1,119 @@
+use rustix::net::{ipproto, AddressFamily, SocketType};
+
+use super::{ProbeError, SocketBackend, Step};
+
+/// The `IPV6_V6ONLY` behaviour of new IPv6 sockets, which is what code that
+/// never sets the option, e.g. many third-party libraries, gets.
+///
+/// See [`try_v6only`](crate::try_v6only).
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct V6OnlyReport {
+  #[cfg_attr(
+    feature = "serde",
+    serde(rename = "default_v6only", with = "crate::snapshot::result")
+  )]
+  default: Result<bool, ProbeError>,
+  #[cfg_attr(feature = "serde", serde(with = "crate::snapshot::check"))]
+  dual_stack: Result<(), ProbeError>,
+  bindv6only: Option<bool>,
+}
+
+impl V6OnlyReport {
+  /// Returns the value of `IPV6_V6ONLY` of a freshly created IPv6 TCP
+  /// socket.
+  ///
+  /// If `true`, IPv6 listeners which do not disable the option explicitly
+  /// never accept IPv4 clients.
+  #[inline]
+  pub const fn default_v6only(&self) -> Result<bool, ProbeError> {
+    self.default
+  }
+
+  /// Returns the result of disabling `IPV6_V6ONLY` on a freshly created
+  /// IPv6 TCP socket.
+  #[inline]
+  pub const fn dual_stack(&self) -> Result<(), ProbeError> {
+    self.dual_stack
+  }
+
+  /// Returns `true` if IPv6 sockets can be created, but disabling
+  /// `IPV6_V6ONLY` is rejected, so IPv6 sockets can never be dual-stack,
+  /// e.g. on OpenBSD.
+  #[inline]
+  pub fn dual_stack_rejected(&self) -> bool {
+    matches!(self.dual_stack, Err(err) if err.step() == Step::SetIpv6V6Only)
+  }
+
+  /// Returns `net.ipv6.bindv6only`, which sets the default on Linux, or
+  /// `None` if it cannot be read or on other platforms.
+  #[inline]
+  pub const fn bindv6only(&self) -> Option<bool> {
+    self.bindv6only
+  }
+}
+
+/// Reads the default of `IPV6_V6ONLY` and tries to disable it on fresh
+/// sockets.
+pub(crate) fn check<B: SocketBackend>(backend: &B, bindv6only: Option<bool>) -> V6OnlyReport {
+  let new_socket = || {
+    backend
+      .socket(AddressFamily::INET6, SocketType::STREAM, Some(ipproto::TCP))
+      .map_err(|e| ProbeError::new(Step::Socket, e, None))
+  };
+
+  let default = new_socket().and_then(|sock| {
+    backend
+      .ipv6_v6only(&sock)
+      .map_err(|e| ProbeError::new(Step::GetIpv6V6Only, e, None))
+  });
+  // Use another socket, reading the option must not affect the result.
+  let dual_stack = new_socket().and_then(|sock| {
+    backend
+      .set_ipv6_v6only(&sock, false)
+      .map_err(|e| ProbeError::new(Step::SetIpv6V6Only, e, None))
+  });
+
+  V6OnlyReport {
+    default,
+    dual_stack,
+    bindv6only,
+  }
+}
+
+/// Reads `net.ipv6.bindv6only` where it exists.
+pub(crate) fn bindv6only() -> Option<bool> {
+  #[cfg(target_os = "linux")]
+  return crate::sysctl::bindv6only();
+
+  #[cfg(not(target_os = "linux"))]
+  None
+}
+
+#[test]
+fn test_v6only() {
+  let report = check(&crate::RustixBackend, bindv6only());
+  // IPv6 sockets can be created even if IPv6 is disabled, e.g. with
+  // `disable_ipv6=1`, so only the socket step of the IPv6 check counts.
+  let tcp = crate::ProbeBuilder::new()
+    .with_udp(false)
+    .report()
+    .tcp()
+    .unwrap();
+  let socket = !matches!(tcp.ipv6(), Err(e) if e.step() == Step::Socket);
+  assert_eq!(report.default_v6only().is_ok(), socket);
+  if tcp.ipv4_mapped_ipv6().is_ok() {
+    assert_eq!(report.dual_stack(), Ok(()));
+  }
+  if let (Ok(default), Some(bindv6only)) = (report.default_v6only(), report.bindv6only()) {
+    assert_eq!(default, bindv6only);
+  }
+
+  let err = ProbeError::new(Step::SetIpv6V6Only, rustix::io::Errno::INVAL, None);
+  let rejected = V6OnlyReport {
+    default: Ok(true),
+    dual_stack: Err(err),
+    bindv6only: None,
+  };
+  assert!(rejected.dual_stack_rejected());
+}
@@ -0,0 +1,286 @@
+//! Live notifications of IP stack capability changes, driven by rtnetlink.
+
+use std::io;
+
+use rustix::{fd::OwnedFd, net::SocketFlags};
+
+use super::{
+  diff,
+  netlink::{self, Header},
+  try_probe, Probe, ProbeDiff, ProbeReport,
+};
+
+const GROUPS: u32 =
+  netlink::RTMGRP_LINK | netlink::RTMGRP_IPV4_IFADDR | netlink::RTMGRP_IPV6_IFADDR;
+
+const BUF_SIZE: usize = 8192;
+
+/// A change of the IP stack capabilities observed by a [`Watcher`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ProbeChange {
+  /// The capabilities before the change.
+  pub old: Probe,
+  /// The capabilities after the change.
+  pub new: Probe,
+  /// The capabilities which changed, with the failed checks of those which
+  /// became unavailable.
+  pub diff: ProbeDiff,
+}
+
+/// Watches the host for address and link changes, and re-probes the
+/// system when one happens.
+///
+/// `Watcher` is a blocking iterator which yields a [`ProbeChange`] every time
+/// the capabilities differ from the previous ones. Events which do not change
+/// the capabilities are swallowed.
+///
+/// The global cached result is not touched, call [`refresh`](crate::refresh)
+/// to update it.
+#[derive(Debug)]
+pub struct Watcher {
+  fd: OwnedFd,
+  current: ProbeReport,
+  buf: Box<[u8]>,
+}
+
+impl Watcher {
+  /// Subscribes to rtnetlink address and link notifications, and probes
+  /// the current capabilities.
+  pub fn new() -> io::Result<Self> {
+    let fd = netlink::open(GROUPS, SocketFlags::empty())?;
+    Ok(Self {
+      fd,
+      current: try_probe(),
+      buf: vec![0; BUF_SIZE].into_boxed_slice(),
+    })
+  }
+
+  /// Returns the latest observed capabilities.
+  #[inline]
+  pub const fn current(&self) -> Probe {
+    self.current.probe()
+  }
+
+  /// Returns the report of the latest observed capabilities.
+  #[inline]
+  pub const fn current_report(&self) -> &ProbeReport {
+    &self.current
+  }
+
+  /// Blocks until the capabilities change.
+  pub fn next_change(&mut self) -> io::Result<ProbeChange> {
+    loop {
+      let mut relevant = netlink::recv_blocking(&self.fd, &mut self.buf, is_relevant)?;
+      // Coalesce bursts of events, e.g. all addresses of a link going away.
+      relevant |= netlink::drain(&self.fd, &mut self.buf, is_relevant)?;
+
+      if relevant {
+        if let Some(change) = update(&mut self.current, try_probe()) {
+          return Ok(change);
+        }
+      }
+    }
+  }
+}
+
+impl Iterator for Watcher {
+  type Item = io::Result<ProbeChange>;
+
+  #[inline]
+  fn next(&mut self) -> Option<Self::Item> {
+    Some(self.next_change())
+  }
+}
+
+/// Creates a [`Watcher`].
+#[inline]
+pub fn watch() -> io::Result<Watcher> {
+  Watcher::new()
+}
+
+fn is_relevant(hdr: &Header) -> bool {
+  matches!(
+    hdr.ty,
+    netlink::RTM_NEWLINK
+      | netlink::RTM_DELLINK
+      | netlink::RTM_NEWADDR
+      | netlink::RTM_DELADDR
+      | netlink::NLMSG_OVERRUN
+  )
+}
+
+/// Replaces `current` with `report`, returns the change if the
+/// capabilities differ.
+fn update(current: &mut ProbeReport, report: ProbeReport) -> Option<ProbeChange> {
+  let (old, new) = (current.probe(), report.probe());
+  if new == old {
+    return None;
+  }
+
+  let diff = diff(current, &report);
+  *current = report;
+  Some(ProbeChange { old, new, diff })
+}
+
+#[cfg(feature = "tokio")]
+pub use r#async::AsyncWatcher;
+
+#[cfg(feature = "tokio")]
+mod r#async {
+  use core::{
+    future::Future,
+    pin::Pin,
+    task::{ready, Context, Poll},
+  };
+
+  use tokio::{
+    io::unix::AsyncFd,
+    task::{JoinError, JoinHandle},
+  };
+
+  use super::*;
+
+  /// The asynchronous version of [`Watcher`], which is also a
+  /// [`Stream`](futures_core::Stream) of [`ProbeChange`]s.
+  ///
+  /// Must be created and polled within a tokio runtime. The checks run on
+  /// the blocking thread pool.
+  #[derive(Debug)]
+  pub struct AsyncWatcher {
+    fd: AsyncFd<OwnedFd>,
+    current: ProbeReport,
+    buf: Box<[u8]>,
+    probing: Option<JoinHandle<ProbeReport>>,
+    /// Whether a relevant event arrived since the last probe started.
+    stale: bool,
+  }
+
+  impl AsyncWatcher {
+    /// Subscribes to rtnetlink address and link notifications, and probes
+    /// the current capabilities on the blocking thread pool.
+    pub async fn new() -> io::Result<Self> {
+      let fd = AsyncFd::new(netlink::open(GROUPS, SocketFlags::NONBLOCK)?)?;
+      let current = joined(tokio::task::spawn_blocking(try_probe).await)?;
+      Ok(Self {
+        fd,
+        current,
+        buf: vec![0; BUF_SIZE].into_boxed_slice(),
+        probing: None,
+        stale: false,
+      })
+    }
+
+    /// Returns the latest observed capabilities.
+    #[inline]
+    pub const fn current(&self) -> Probe {
+      self.current.probe()
+    }
+
+    /// Returns the report of the latest observed capabilities.
+    #[inline]
+    pub const fn current_report(&self) -> &ProbeReport {
+      &self.current
+    }
+
+    /// Waits until the capabilities change.
+    pub async fn next_change(&mut self) -> io::Result<ProbeChange> {
+      core::future::poll_fn(|cx| self.poll_next_change(cx)).await
+    }
+
+    /// Polls for the next change of the capabilities.
+    pub fn poll_next_change(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<ProbeChange>> {
+      loop {
+        // Keep draining while probing, events arriving meanwhile trigger
+        // another probe.
+        if let Poll::Ready(guard) = self.fd.poll_read_ready(cx) {
+          let mut guard = guard?;
+          self.stale |= netlink::drain(guard.get_inner(), &mut self.buf, is_relevant)?;
+          // `drain` reads until the socket would block.
+          guard.clear_ready();
+          continue;
+        }
+
+        if let Some(probing) = &mut self.probing {
+          let res = ready!(Pin::new(probing).poll(cx));
+          self.probing = None;
+          let report = joined(res)?;
+          if let Some(change) = update(&mut self.current, report) {
+            return Poll::Ready(Ok(change));
+          }
+          continue;
+        }
+
+        if !core::mem::take(&mut self.stale) {
+          return Poll::Pending;
+        }
+        self.probing = Some(tokio::task::spawn_blocking(try_probe));
+      }
+    }
+  }
+
+  /// Returns the report of a finished probe, resumes its panic.
+  fn joined(res: Result<ProbeReport, JoinError>) -> io::Result<ProbeReport> {
+    res.map_err(|e| match e.try_into_panic() {
+      Ok(panic) => std::panic::resume_unwind(panic),
+      Err(e) => io::Error::new(io::ErrorKind::Other, e),
+    })
+  }
+
+  impl futures_core::Stream for AsyncWatcher {
+    type Item = io::Result<ProbeChange>;
+
+    #[inline]
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+      self.get_mut().poll_next_change(cx).map(Some)
+    }
+  }
+}
+
+#[test]
+fn test_relevant() {
+  let mut buf = netlink::message(3, &[]);
+  assert!(!netlink::Messages::new(&buf).any(|(hdr, _)| is_relevant(&hdr)));
+
+  buf.extend(netlink::message(netlink::RTM_DELADDR, &[]));
+  assert!(netlink::Messages::new(&buf).any(|(hdr, _)| is_relevant(&hdr)));
+}
+
+#[test]
+fn test_update() {
+  use rustix::io::Errno;
+
+  use crate::{Capabilities, MockBackend, ProbeBuilder, Step};
+
+  let builder = ProbeBuilder::new().with_udp(false).with_v6only(false);
+  let dual = builder.with_backend(MockBackend::new()).report();
+  let ipv4_only = builder
+    .with_backend(MockBackend::new().with_v6only_error(true, Step::Bind, Errno::ADDRNOTAVAIL))
+    .report();
+
+  let mut current = dual.clone();
+  assert_eq!(update(&mut current, dual.clone()), None);
+
+  let change = update(&mut current, ipv4_only.clone()).unwrap();
+  assert_eq!(change.old, dual.probe());
+  assert_eq!(change.new, ipv4_only.probe());
+  assert_eq!(change.diff, diff(&dual, &ipv4_only));
+  assert_eq!(change.diff.lost(), Capabilities::IPV6);
+  assert_eq!(current, ipv4_only);
+}
+
+#[test]
+fn test_watcher() {
+  if let Some(watcher) = netlink::allowed(watch()) {
+    assert_eq!(watcher.current(), crate::probe_fresh());
+    assert_eq!(watcher.current_report().probe(), watcher.current());
+  }
+}
+
+#[cfg(all(test, feature = "tokio"))]
+#[tokio::test]
+async fn test_async_watcher() {
+  if let Some(watcher) = netlink::allowed(AsyncWatcher::new().await) {
+    assert_eq!(watcher.current(), crate::probe_fresh());
+  }
+}
@@ -0,0 +1,43 @@
+//! The overrides are global, the checks run in their own test binary so
+//! that they do not race the unit tests which call `probe`.
+
+use iprobe::{
+  clear_override, overrides, probe, probe_fresh, scoped_override, set_override, Capabilities, Probe,
+};
+
+#[test]
+fn test_overrides() {
+  let env = overrides();
+  assert_eq!(env.probe(), None);
+  let probed = env.apply(probe_fresh());
+  assert_eq!(probe(), probed);
+
+  let ipv4 = Probe::from_capabilities(Capabilities::IPV4 | Capabilities::UDP_IPV4);
+  let none = Probe::from_capabilities(Capabilities::empty());
+  {
+    let _outer = scoped_override(ipv4);
+    assert_eq!(probe(), ipv4);
+    assert_eq!(overrides().probe(), Some(ipv4));
+    {
+      let _inner = scoped_override(none);
+      assert_eq!(probe(), none);
+      assert!(!iprobe::ipv4());
+    }
+    assert_eq!(probe(), ipv4);
+  }
+  assert_eq!(overrides(), env);
+  assert_eq!(probe(), probed);
+
+  assert_eq!(set_override(none), None);
+  assert_eq!(set_override(ipv4), Some(none));
+  assert_eq!(probe(), ipv4);
+  assert_eq!(clear_override(), Some(ipv4));
+  assert_eq!(clear_override(), None);
+  assert_eq!(probe(), probed);
+
+  let report = iprobe::try_probe();
+  assert_eq!(report.overrides(), env);
+  let _guard = scoped_override(none);
+  assert_eq!(iprobe::try_probe().overrides().probe(), Some(none));
+  assert_eq!(iprobe::try_probe().probe(), report.probe());
+}